@@ -21,7 +21,9 @@ in
 
     installPhase = ''
       mkdir -p $out
-      persona check
+      # The sandbox has none of the tools `when` blocks probe for; pin the outcome
+      # AGENTS.md was built with
+      persona --assume binary:jj check
       touch $out/pass
     '';
   }
@@ -0,0 +1,329 @@
+//! Shell completion. The bash, zsh and fish scripts call back into `persona` with
+//! `COMPLETE=<shell>` set (see [`clap_complete::CompleteEnv`]), and entity names, category
+//! paths, tags and filters are completed from the inputs given on the command line being
+//! completed. Nushell gets a static script from `clap_complete_nushell`, which completes
+//! subcommands, options and fixed choices only.
+use clap::{ArgMatches, CommandFactory};
+use clap_complete::CompletionCandidate;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Zsh};
+use persona_core::bundle::PERSONA_KIND;
+use persona_core::names::DuplicateNames;
+use persona_core::resolve::find_entity;
+use persona_core::{
+    EntityOrHeader, HierarchyRow, ParsedEntity, collect_entities_with_diagnostics, hierarchy,
+};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::cli::{Cli, Shell};
+
+/// Writes the completion script for `shell`. The scripts run `persona` from `PATH`
+/// rather than the binary that wrote them, so they survive upgrades.
+pub fn script(shell: Shell, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut command = Cli::command();
+    let bin = command.get_name().to_string();
+    match shell {
+        Shell::Bash => Bash.write_registration("COMPLETE", &bin, &bin, &bin, out),
+        Shell::Zsh => Zsh.write_registration("COMPLETE", &bin, &bin, &bin, out),
+        Shell::Fish => Fish.write_registration("COMPLETE", &bin, &bin, &bin, out),
+        Shell::Nushell => {
+            clap_complete::generate(clap_complete_nushell::Nushell, &mut command, bin, out);
+            Ok(())
+        }
+    }
+}
+
+/// Entity names, for `show`, `mv` and `graph --focus`.
+pub fn entities() -> Vec<CompletionCandidate> {
+    let context = Context::current();
+    entity_candidates(&context.rows(), None)
+}
+
+/// Persona names, for `build --persona`.
+pub fn personas() -> Vec<CompletionCandidate> {
+    let context = Context::current();
+    entity_candidates(&context.rows(), Some(PERSONA_KIND))
+}
+
+/// Category paths of the kind being created or moved, for `new` and the `mv` destination.
+pub fn categories() -> Vec<CompletionCandidate> {
+    let context = Context::current();
+    category_candidates(&context.rows(), context.kind().as_deref())
+}
+
+/// Tags used by any entity, for `--tag` and `--exclude-tag`.
+pub fn tags() -> Vec<CompletionCandidate> {
+    tag_candidates(&Context::current().items)
+}
+
+/// Simple filter expressions over the kinds, categories and tags in use, for `--filter`.
+pub fn filters() -> Vec<CompletionCandidate> {
+    let context = Context::current();
+    filter_candidates(&context.rows())
+}
+
+/// The command line being completed, parsed as far as it goes, and the entities of its
+/// inputs. Invalid inputs still yield the entities that parsed; nothing is reported.
+struct Context {
+    matches: ArgMatches,
+    inputs: Vec<PathBuf>,
+    items: Vec<EntityOrHeader>,
+}
+
+impl Context {
+    /// The completion scripts run `persona -- <words>` with the words being completed.
+    fn current() -> Self {
+        Self::new(std::env::args_os().skip_while(|arg| arg != "--").skip(1))
+    }
+
+    fn new(words: impl IntoIterator<Item = OsString>) -> Self {
+        let matches = Cli::command()
+            .ignore_errors(true)
+            .try_get_matches_from(words)
+            .unwrap_or_default();
+        // Global options given after the subcommand are only in the subcommand's matches.
+        let inputs = matches
+            .subcommand()
+            .map_or(&matches, |(_, args)| args)
+            .try_get_many::<PathBuf>("input")
+            .ok()
+            .flatten()
+            .map(|inputs| inputs.cloned().collect())
+            .unwrap_or_else(|| vec![PathBuf::from(".agent")]);
+        let items = collect_entities_with_diagnostics(
+            &inputs,
+            u64::MAX,
+            u64::MAX,
+            DuplicateNames::default(),
+        )
+        .items;
+        Self {
+            matches,
+            inputs,
+            items,
+        }
+    }
+
+    fn rows(&self) -> Vec<HierarchyRow<'_>> {
+        hierarchy(&self.items, &self.inputs)
+    }
+
+    /// The kind named by `new <KIND>`, or the kind of the entity `mv` moves.
+    fn kind(&self) -> Option<String> {
+        let arg =
+            |args: &ArgMatches, id: &str| args.try_get_one::<String>(id).ok().flatten().cloned();
+        match self.matches.subcommand()? {
+            ("new", args) => arg(args, "kind"),
+            ("mv", args) => find_entity(&self.items, &self.inputs, &arg(args, "entity")?)
+                .ok()
+                .map(ParsedEntity::kind),
+            _ => None,
+        }
+    }
+}
+
+/// Candidates sorted by value and without duplicates, with their help text.
+fn sorted(candidates: BTreeMap<String, Option<String>>) -> Vec<CompletionCandidate> {
+    candidates
+        .into_iter()
+        .map(|(value, help)| CompletionCandidate::new(value).help(help.map(Into::into)))
+        .collect()
+}
+
+/// The kind directory of a row, e.g. `skills`.
+fn kind_dir(row: &HierarchyRow) -> String {
+    row.path
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn entity_candidates(rows: &[HierarchyRow], kind: Option<&str>) -> Vec<CompletionCandidate> {
+    sorted(
+        rows.iter()
+            .filter_map(|row| row.entity)
+            .filter(|entity| kind.is_none_or(|kind| entity.kind() == kind))
+            .map(|entity| {
+                let description = entity.frontmatter.description.lines().next();
+                (
+                    entity.frontmatter.name.clone(),
+                    description.map(|d| d.trim().to_string()),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Category paths relative to the kind directory with a trailing `/`, as `new` and `mv`
+/// take them; only those of `kind` when given.
+fn category_candidates(rows: &[HierarchyRow], kind: Option<&str>) -> Vec<CompletionCandidate> {
+    sorted(
+        rows.iter()
+            .filter(|row| row.entity.is_none())
+            .filter(|row| kind.is_none_or(|kind| kind_dir(row) == format!("{}s", kind)))
+            .filter_map(|row| {
+                let category: Vec<_> = row
+                    .path
+                    .components()
+                    .skip(1)
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                (!category.is_empty()).then(|| (format!("{}/", category.join("/")), None))
+            })
+            .collect(),
+    )
+}
+
+fn tag_candidates(items: &[EntityOrHeader]) -> Vec<CompletionCandidate> {
+    sorted(
+        items
+            .iter()
+            .filter_map(|item| match item {
+                EntityOrHeader::Entity(e) => Some(e),
+                EntityOrHeader::Header(_) => None,
+            })
+            .flat_map(|entity| &entity.frontmatter.tags)
+            .map(|tag| (tag.clone(), None))
+            .collect(),
+    )
+}
+
+fn filter_candidates(rows: &[HierarchyRow]) -> Vec<CompletionCandidate> {
+    let mut candidates = BTreeMap::new();
+    for row in rows {
+        let Some(entity) = row.entity else {
+            continue;
+        };
+        candidates.insert(format!("kind = {}", entity.kind()), None);
+        if let Some(category) = row.path.parent().filter(|c| !c.as_os_str().is_empty()) {
+            let category = category.to_string_lossy().replace('\\', "/");
+            candidates.insert(format!("category = {}", category), None);
+        }
+        for tag in &entity.frontmatter.tags {
+            candidates.insert(format!("tags = {}", tag), None);
+        }
+    }
+    sorted(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_core::{Frontmatter, Header};
+    use std::path::Path;
+
+    fn entity(path: &str, tags: &[&str]) -> EntityOrHeader {
+        let name = Path::new(path)
+            .parent()
+            .and_then(|p| p.file_name())
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name,
+                description: "d".to_string(),
+                tags: tags.iter().map(ToString::to_string).collect(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 1,
+        })
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity("/in/skills/vcs/git/SKILL.md", &["vcs"]),
+            entity("/in/skills/vcs/jj/SKILL.md", &["vcs", "jj"]),
+            entity("/in/personas/eng/qa/PERSONA.md", &[]),
+            EntityOrHeader::Header(Header {
+                path: PathBuf::from("/in/skills/HEADER.md"),
+                ..Default::default()
+            }),
+        ]
+    }
+
+    fn values(candidates: Vec<CompletionCandidate>) -> Vec<String> {
+        candidates
+            .iter()
+            .map(|c| c.get_value().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_candidates() {
+        let items = items();
+        let rows = hierarchy(&items, &[PathBuf::from("/in")]);
+        assert_eq!(
+            values(entity_candidates(&rows, None)),
+            vec!["git", "jj", "qa"]
+        );
+        assert_eq!(
+            values(entity_candidates(&rows, Some(PERSONA_KIND))),
+            vec!["qa"]
+        );
+        assert_eq!(
+            values(category_candidates(&rows, None)),
+            vec!["eng/", "vcs/"]
+        );
+        assert_eq!(
+            values(category_candidates(&rows, Some("skill"))),
+            vec!["vcs/"]
+        );
+        assert_eq!(values(tag_candidates(&items)), vec!["jj", "vcs"]);
+        assert_eq!(
+            values(filter_candidates(&rows)),
+            vec![
+                "category = personas/eng",
+                "category = skills/vcs",
+                "kind = persona",
+                "kind = skill",
+                "tags = jj",
+                "tags = vcs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_context_kind() {
+        let temp = tempfile::tempdir().unwrap();
+        let input = temp.path().join("in");
+        let skill = input.join("skills/vcs/git");
+        std::fs::create_dir_all(&skill).unwrap();
+        std::fs::write(
+            skill.join("SKILL.md"),
+            "---\nname: git\ndescription: Git\n---\nBody\n",
+        )
+        .unwrap();
+        let context = |words: &[&str]| {
+            let mut line = vec!["persona", "-i", input.to_str().unwrap()];
+            line.extend(words);
+            Context::new(line.into_iter().map(OsString::from))
+        };
+
+        assert_eq!(
+            context(&["new", "skill", ""]).kind().as_deref(),
+            Some("skill")
+        );
+        assert_eq!(context(&["mv", "git", ""]).kind().as_deref(), Some("skill"));
+        assert_eq!(context(&["mv", "nothing", ""]).kind(), None);
+        assert_eq!(context(&["show", ""]).kind(), None);
+        assert_eq!(
+            values(entity_candidates(&context(&["show", ""]).rows(), None)),
+            vec!["git"]
+        );
+        let inputs_after = Context::new(
+            ["persona", "show", "-i", input.to_str().unwrap(), ""]
+                .into_iter()
+                .map(OsString::from),
+        );
+        assert_eq!(
+            values(entity_candidates(&inputs_after.rows(), None)),
+            vec!["git"]
+        );
+    }
+}
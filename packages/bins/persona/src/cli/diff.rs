@@ -0,0 +1,277 @@
+use anstyle::{AnsiColor, Style};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const CONTEXT_LINES: usize = 3;
+
+const HEADER: Style = Style::new().bold();
+const HUNK: Style = AnsiColor::Cyan.on_default();
+const REMOVED: Style = AnsiColor::Red.on_default();
+const ADDED: Style = AnsiColor::Green.on_default();
+
+/// Renders a unified diff from the `current` file contents to the `expected` contents.
+///
+/// Hunk headers name the entity element the hunk starts in (like `git diff` does for
+/// functions), and a summary of every changed entity follows the diff.
+pub fn unified_diff(current: &str, expected: &str, name: &str, color: bool) -> String {
+    let diff = TextDiff::from_lines(current, expected);
+    let current_entities = entity_contexts(current);
+    let expected_entities = entity_contexts(expected);
+    let paint = |style: Style, text: &str| {
+        if color {
+            format!("{}{}{}", style.render(), text, style.render_reset())
+        } else {
+            text.to_string()
+        }
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", paint(HEADER, &format!("--- {} (current)", name)));
+    let _ = writeln!(
+        out,
+        "{}",
+        paint(HEADER, &format!("+++ {} (expected from inputs)", name))
+    );
+
+    let mut changed = BTreeSet::new();
+    for group in diff.grouped_ops(CONTEXT_LINES) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_start = first.old_range().start;
+        let old_len = last.old_range().end - old_start;
+        let new_start = first.new_range().start;
+        let new_len = last.new_range().end - new_start;
+
+        let mut context = None;
+        let mut lines = String::new();
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let (sign, style) = match change.tag() {
+                    ChangeTag::Equal => (' ', None),
+                    ChangeTag::Delete => ('-', Some(REMOVED)),
+                    ChangeTag::Insert => ('+', Some(ADDED)),
+                };
+                let entity = match (change.old_index(), change.new_index()) {
+                    (Some(i), _) if change.tag() == ChangeTag::Delete => {
+                        current_entities[i].clone()
+                    }
+                    (_, Some(i)) if change.tag() == ChangeTag::Insert => {
+                        expected_entities[i].clone()
+                    }
+                    _ => None,
+                };
+                if let Some(entity) = entity {
+                    context.get_or_insert_with(|| entity.clone());
+                    changed.insert(entity);
+                }
+
+                let line = format!("{}{}", sign, change.value().trim_end_matches(['\n', '\r']));
+                let _ = match style {
+                    Some(style) => writeln!(lines, "{}", paint(style, &line)),
+                    None => writeln!(lines, "{}", line),
+                };
+                if change.missing_newline() {
+                    let _ = writeln!(lines, "\\ No newline at end of file");
+                }
+            }
+        }
+
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        );
+        if let Some(context) = context {
+            header.push(' ');
+            header.push_str(&context);
+        }
+        let _ = writeln!(out, "{}", paint(HUNK, &header));
+        out.push_str(&lines);
+    }
+
+    if !changed.is_empty() {
+        let _ = writeln!(out, "Changed entities:");
+        for entity in changed {
+            let _ = writeln!(out, "  {}", entity);
+        }
+    }
+    out
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    // Unified diff line numbers are 1-based; empty ranges point at the line before.
+    let start = if len == 0 { start } else { start + 1 };
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+/// For each line, the entity element (by `path` attribute) that encloses it, if any.
+/// The element ends at its own closing tag, counting nested elements of the same name.
+fn entity_contexts(text: &str) -> Vec<Option<String>> {
+    let mut contexts = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    let mut depth = 0;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if current.is_none() {
+            current = entity_start(trimmed);
+        }
+        contexts.push(current.as_ref().map(|(_, label)| label.clone()));
+
+        if let Some((tag, _)) = &current {
+            depth += nesting(trimmed, tag);
+            if depth <= 0 {
+                current = None;
+                depth = 0;
+            }
+        }
+    }
+    // `lines()` drops a trailing empty line that the diff still counts.
+    contexts.push(None);
+    contexts
+}
+
+/// How many `tag` elements the line opens minus how many it closes; self-closing
+/// elements count as neither.
+fn nesting(line: &str, tag: &str) -> isize {
+    let mut change = 0;
+    for (i, _) in line.match_indices('<') {
+        let rest = &line[i + 1..];
+        if let Some(closing) = rest.strip_prefix('/') {
+            if closing
+                .strip_prefix(tag)
+                .is_some_and(|r| r.starts_with('>'))
+            {
+                change -= 1;
+            }
+            continue;
+        }
+        let Some(after) = rest.strip_prefix(tag) else {
+            continue;
+        };
+        if !after.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            continue;
+        }
+        if let Some(end) = after.find('>')
+            && !after[..end].ends_with('/')
+        {
+            change += 1;
+        }
+    }
+    change
+}
+
+/// Parses an opening tag with a `path` attribute into its tag name and a label.
+fn entity_start(line: &str) -> Option<(String, String)> {
+    let rest = line.strip_prefix('<')?;
+    if rest.starts_with('/') {
+        return None;
+    }
+    let tag: String = rest
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '>' && *c != '/')
+        .collect();
+    let path = attribute(line, "path")?;
+    let name = attribute(line, "name").unwrap_or_else(|| tag.clone());
+    Some((tag, format!("{} ({})", name, path)))
+}
+
+fn attribute(line: &str, name: &str) -> Option<String> {
+    let needle = format!(" {}=\"", name);
+    let start = line.find(&needle)? + needle.len();
+    let end = line[start..].find('"')? + start;
+    Some(line[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURRENT: &str = r#"<persona-context>
+  <skills>
+    <rust path="skills/rust/SKILL.md">
+      <description>Rust programming skill</description>
+    </rust>
+    <python path="skills/python/SKILL.md">
+      <description>Python skill</description>
+    </python>
+  </skills>
+</persona-context>"#;
+
+    #[test]
+    fn test_unified_diff_names_changed_entities() {
+        let expected = CURRENT.replace("Python skill", "Python scripting skill");
+
+        let diff = unified_diff(CURRENT, &expected, "AGENTS.md", false);
+
+        assert!(
+            diff.starts_with("--- AGENTS.md (current)\n+++ AGENTS.md (expected from inputs)\n")
+        );
+        assert!(diff.contains("@@ -4,7 +4,7 @@ python (skills/python/SKILL.md)\n"));
+        assert!(diff.contains("\n-      <description>Python skill</description>\n"));
+        assert!(diff.contains("\n+      <description>Python scripting skill</description>\n"));
+        assert!(diff.ends_with("Changed entities:\n  python (skills/python/SKILL.md)\n"));
+        assert!(!diff.contains("\u{1b}["));
+    }
+
+    #[test]
+    fn test_unified_diff_trailing_newline() {
+        let current = format!("{}\n", CURRENT);
+
+        let diff = unified_diff(&current, CURRENT, "AGENTS.md", false);
+
+        assert!(
+            diff.contains(
+                "-</persona-context>\n+</persona-context>\n\\ No newline at end of file\n"
+            )
+        );
+        assert!(!diff.contains("Changed entities"));
+    }
+
+    #[test]
+    fn test_unified_diff_color() {
+        let expected = CURRENT.replace("Python skill", "Python scripting skill");
+
+        let diff = unified_diff(CURRENT, &expected, "AGENTS.md", true);
+
+        assert!(diff.contains(&format!(
+            "{}-      <description>Python skill</description>{}",
+            REMOVED.render(),
+            REMOVED.render_reset()
+        )));
+    }
+
+    #[test]
+    fn test_entity_contexts_end_at_matching_tag() {
+        let text = r#"<skills>
+  <rust path="skills/rust/SKILL.md">
+    <resource href="checklist.md"/>
+    <rust>nested</rust>
+    <description>Rust</description>
+  </rust>
+  <go path="skills/go/SKILL.md"/>
+</skills>"#;
+
+        let rust = Some("rust (skills/rust/SKILL.md)".to_string());
+        let go = Some("go (skills/go/SKILL.md)".to_string());
+        assert_eq!(
+            entity_contexts(text),
+            vec![
+                None,
+                rust.clone(),
+                rust.clone(),
+                rust.clone(),
+                rust.clone(),
+                rust,
+                go,
+                None,
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use persona_core::PersonaError;
+use std::fmt;
+
+/// Process exit codes, one per class of failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// Any failure not covered below.
+    Failure = 1,
+    /// Invalid command line; reported by the argument parser.
+    Usage = 2,
+    /// Input files or templates failed validation.
+    InvalidInputs = 3,
+    /// A file exceeds the token error limit.
+    TokenBudget = 4,
+    /// The agents file is missing or out of date.
+    Stale = 5,
+    /// A file could not be read or written.
+    Io = 6,
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code as u8)
+    }
+}
+
+/// The agents file is missing or does not match what the inputs generate.
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub struct OutOfDate(pub String);
+
+/// A failed command together with the exit code it maps to.
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ExitCode,
+    pub error: anyhow::Error,
+}
+
+impl From<anyhow::Error> for CliError {
+    fn from(error: anyhow::Error) -> Self {
+        let code = error
+            .chain()
+            .find_map(|cause| {
+                if cause.is::<OutOfDate>() {
+                    Some(ExitCode::Stale)
+                } else if let Some(e) = cause.downcast_ref::<PersonaError>() {
+                    Some(match e {
+                        _ if e.is_token_budget() => ExitCode::TokenBudget,
+                        _ if e.is_io() => ExitCode::Io,
+                        PersonaError::DirectoryNotFound(_)
+                        | PersonaError::InvalidPath(_)
+                        | PersonaError::NameTaken { .. }
+                        | PersonaError::Validation { .. }
+                        | PersonaError::Template { .. } => ExitCode::InvalidInputs,
+                        PersonaError::Parse(_) => ExitCode::InvalidInputs,
+                        _ => ExitCode::Failure,
+                    })
+                } else if cause.is::<std::io::Error>() {
+                    Some(ExitCode::Io)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(ExitCode::Failure);
+        Self { code, error }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let code = |error: anyhow::Error| CliError::from(error).code;
+
+        assert_eq!(
+            code(
+                PersonaError::Validation {
+                    rules: vec!["invalid-yaml", "token-limit"]
+                }
+                .into()
+            ),
+            ExitCode::InvalidInputs
+        );
+        assert_eq!(
+            code(
+                PersonaError::Validation {
+                    rules: vec!["token-limit"]
+                }
+                .into()
+            ),
+            ExitCode::TokenBudget
+        );
+        assert_eq!(
+            code(
+                PersonaError::Validation {
+                    rules: vec!["io-error"]
+                }
+                .into()
+            ),
+            ExitCode::Io
+        );
+        assert_eq!(
+            code(
+                PersonaError::Validation {
+                    rules: vec!["io-error", "invalid-yaml"]
+                }
+                .into()
+            ),
+            ExitCode::InvalidInputs
+        );
+        assert_eq!(
+            code(PersonaError::InvalidPath("../escape".to_string()).into()),
+            ExitCode::InvalidInputs
+        );
+        assert_eq!(
+            code(PersonaError::TokenBudget("too big".to_string()).into()),
+            ExitCode::TokenBudget
+        );
+        assert_eq!(
+            code(OutOfDate("AGENTS.md is out of date".to_string()).into()),
+            ExitCode::Stale
+        );
+        assert_eq!(
+            code(std::io::Error::other("disk full").into()),
+            ExitCode::Io
+        );
+        assert_eq!(
+            code(anyhow::Error::from(std::io::Error::other("denied")).context("writing report")),
+            ExitCode::Io
+        );
+        assert_eq!(code(anyhow::anyhow!("something else")), ExitCode::Failure);
+    }
+
+    #[test]
+    fn test_source_is_wrapped_error() {
+        use std::error::Error;
+
+        let error = CliError::from(anyhow::Error::from(std::io::Error::other("disk full")));
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "disk full");
+        assert!(source.downcast_ref::<std::io::Error>().is_some());
+    }
+}
@@ -1,96 +1,672 @@
-use persona_core::{collect_entities, print_hierarchy, xml::generate_xml};
+use persona_core::{
+    Diagnostic, EntityOrHeader, Header, PersonaError, Severity,
+    bundle::{PERSONA_KIND, bundle},
+    collect_entities_with_diagnostics,
+    conditions::Environment,
+    config::Config,
+    filter::Filter,
+    graph::Graph,
+    hierarchy,
+    inspect::EntityDetails,
+    names::DuplicateNames,
+    rename::plan_move,
+    requires::check_kept_requirements,
+    resolve::{find_entity, find_entity_of_kind},
+    scaffold::new_entity,
+    search::{SearchIndex, SearchQuery, Snippet},
+    sort_items,
+    tags::TagFilter,
+    template::render_template,
+    xml::{
+        XmlOptions, generate_xml_with_options,
+        reader::{XmlDifference, compare_xml, parse_xml},
+    },
+};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::IsTerminal;
+use std::path::{Component, Path, PathBuf};
 
-use crate::cli::{Cli, Commands};
+use crate::cli::error::{CliError, OutOfDate};
+use crate::cli::listing::{self, Columns, Listing};
+use crate::cli::{Cli, Commands, GraphFormat, ListFormat, ReportFormat, completions, diff, report};
+
+/// Runs the command, classifying any failure by its exit code.
+pub fn handle_cli(cli: Cli) -> Result<(), CliError> {
+    run(cli).map_err(CliError::from)
+}
 
 #[tracing::instrument(skip(cli))]
-pub fn handle_cli(cli: Cli) -> anyhow::Result<()> {
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let xml_options = XmlOptions {
+        escape: cli.xml_escape.into(),
+        inline_max_tokens: cli.inline_max_tokens,
+        ..Default::default()
+    };
+    let environment = Environment::new(cli.assume);
+    // Only commands reporting on the whole tree read persona.yaml, so a broken or missing
+    // configuration does not get in the way of the others
+    let config_path = cli.config.clone();
+    let duplicate_names = cli.duplicate_names;
+    let load_config = || -> anyhow::Result<(Config, DuplicateNames)> {
+        let config = Config::load(config_path.as_deref())?;
+        let names = duplicate_names.map_or(config.duplicate_names, DuplicateNames::from);
+        Ok((config, names))
+    };
+    let lookup_names = duplicate_names.map_or_else(DuplicateNames::default, DuplicateNames::from);
+
     match cli.command {
-        Commands::Check { agents_file } => {
+        Commands::Check {
+            agents_file,
+            template,
+            diff,
+            semantic,
+            report_format,
+            report,
+        } => {
+            let (config, names) = load_config()?;
+            let options = CheckOptions {
+                agents_file: &agents_file,
+                config: &config,
+                environment: &environment,
+                names,
+                template: template.as_deref(),
+                show_diff: diff,
+                semantic,
+                report_format,
+                report: report.as_deref(),
+            };
             handle_check_command(
                 &cli.input,
-                &agents_file,
+                &options,
+                &xml_options,
                 cli.warn_token_count,
                 cli.error_token_count,
             )?;
         }
-        Commands::List => {
-            handle_list_command(&cli.input, cli.warn_token_count, cli.error_token_count)?;
+        Commands::List {
+            filter,
+            tag,
+            exclude_tag,
+            explain,
+            origin,
+            format,
+            long,
+        } => {
+            let (_, names) = load_config()?;
+            let filter = filter.as_deref().map(Filter::parse).transpose()?;
+            let options = ListOptions {
+                filter: filter.as_ref(),
+                tags: TagFilter::new(tag, exclude_tag),
+                environment: &environment,
+                names,
+                explain,
+                origin,
+                format,
+                long,
+            };
+            handle_list_command(
+                &cli.input,
+                &options,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
         }
-        Commands::Build { output } => {
+        Commands::Build {
+            output,
+            template,
+            filter,
+            tag,
+            exclude_tag,
+            persona,
+            agents_file,
+        } => {
+            let (config, names) = load_config()?;
+            let filter = filter.as_deref().map(Filter::parse).transpose()?;
+            let options = BuildOptions {
+                output: output.as_deref(),
+                agents_file: agents_file.as_deref(),
+                template: template.as_deref(),
+                filter: filter.as_ref(),
+                tags: TagFilter::new(tag, exclude_tag),
+                persona: persona.as_deref(),
+                config: &config,
+                environment: &environment,
+                names,
+                xml_options: &xml_options,
+            };
             handle_build_command(
                 &cli.input,
-                output.as_deref(),
+                &options,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
+        }
+        Commands::New { kind, path } => {
+            handle_new_command(&cli.input, &kind, &path)?;
+        }
+        Commands::Show {
+            entity,
+            json,
+            render,
+        } => {
+            handle_show_command(
+                &cli.input,
+                &entity,
+                json,
+                render.then_some(&xml_options),
+                lookup_names,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
+        }
+        Commands::Search {
+            query,
+            limit,
+            cache,
+        } => {
+            handle_search_command(
+                &cli.input,
+                &query.join(" "),
+                limit,
+                cache.as_deref(),
+                lookup_names,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
+        }
+        Commands::Query { expr } => {
+            let (_, names) = load_config()?;
+            handle_query_command(
+                &cli.input,
+                &expr,
+                names,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
+        }
+        Commands::Graph {
+            format,
+            focus,
+            orphans,
+        } => {
+            let (_, names) = load_config()?;
+            handle_graph_command(
+                &cli.input,
+                format,
+                focus.as_deref(),
+                orphans,
+                names,
+                cli.warn_token_count,
+                cli.error_token_count,
+            )?;
+        }
+        Commands::Mv {
+            entity,
+            destination,
+            dry_run,
+            template,
+        } => {
+            let (config, names) = load_config()?;
+            let rebuild = BuildOptions {
+                output: None,
+                agents_file: None,
+                template: template.as_deref(),
+                filter: None,
+                tags: TagFilter::default(),
+                persona: None,
+                config: &config,
+                environment: &environment,
+                names,
+                xml_options: &xml_options,
+            };
+            handle_mv_command(
+                &cli.input,
+                &entity,
+                &destination,
+                dry_run,
+                &rebuild,
                 cli.warn_token_count,
                 cli.error_token_count,
             )?;
         }
+        Commands::Completions { shell } => {
+            completions::script(shell, &mut std::io::stdout())?;
+        }
     }
     Ok(())
 }
 
+/// Which entities `persona list` prints and how.
+#[derive(Debug)]
+struct ListOptions<'a> {
+    filter: Option<&'a Filter>,
+    tags: TagFilter,
+    environment: &'a Environment,
+    names: DuplicateNames,
+    /// Print the excluded entities with the reasons afterwards.
+    explain: bool,
+    /// Print the inputs each entity came from.
+    origin: bool,
+    format: ListFormat,
+    /// Print the kind, token count, file and description of each entity.
+    long: bool,
+}
+
 #[tracing::instrument]
-fn handle_list_command(inputs: &[PathBuf], warn: u64, error: u64) -> anyhow::Result<()> {
-    let entities = collect_entities(inputs, warn, error)?;
-    print_hierarchy(&entities, inputs, std::io::stdout())?;
+fn handle_list_command(
+    inputs: &[PathBuf],
+    options: &ListOptions,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let ListOptions {
+        filter,
+        ref tags,
+        environment,
+        names,
+        explain,
+        origin,
+        format,
+        long,
+    } = *options;
+
+    let collection = collect_entities_with_diagnostics(inputs, warn, error, names);
+    let origins = collection.origins.clone();
+    let all = collection.into_result()?;
+    let mut entities = tags.apply(environment.apply(all.clone()));
+    if let Some(filter) = filter {
+        entities = filter.apply(entities, inputs);
+    }
+
+    let excluded = explain.then(|| {
+        all.iter()
+            .filter_map(|item| match item {
+                EntityOrHeader::Entity(e) => Some(e),
+                EntityOrHeader::Header(_) => None,
+            })
+            .filter_map(|entity| {
+                let mut reasons = environment.unmet(entity);
+                if reasons.is_empty() && !tags.matches(entity) {
+                    reasons.push("tags do not match --tag/--exclude-tag".to_string());
+                }
+                if reasons.is_empty() && filter.is_some_and(|f| !f.matches(entity, inputs)) {
+                    reasons.push("does not match --filter".to_string());
+                }
+                (!reasons.is_empty()).then_some((entity, reasons))
+            })
+            .collect()
+    });
+    let listing = Listing {
+        rows: hierarchy(&entities, inputs),
+        origins: &origins,
+        excluded,
+    };
+    let columns = Columns {
+        long,
+        origin,
+        color: stdout_color(),
+        width: std::io::stdout()
+            .is_terminal()
+            .then(listing::terminal_width),
+    };
+    print!("{}", listing::render(format, &listing, &columns)?);
     Ok(())
 }
 
+/// How `persona check` compares the agents file against the inputs.
+#[derive(Debug)]
+struct CheckOptions<'a> {
+    agents_file: &'a Path,
+    /// Every profile's file is checked as well.
+    config: &'a Config,
+    /// Entities whose `when` conditions do not hold are left out.
+    environment: &'a Environment,
+    names: DuplicateNames,
+    template: Option<&'a Path>,
+    /// Print the differences to stdout rather than stderr.
+    show_diff: bool,
+    /// Compare parsed XML instead of the exact text.
+    semantic: bool,
+    report_format: Option<ReportFormat>,
+    /// File the report is written to; stdout when unset.
+    report: Option<&'a Path>,
+}
+
 #[tracing::instrument]
 fn handle_check_command(
     inputs: &[PathBuf],
-    agents_file: &Path,
+    options: &CheckOptions,
+    xml_options: &XmlOptions,
     warn: u64,
     error: u64,
 ) -> anyhow::Result<()> {
-    let entities = collect_entities(inputs, warn, error)?;
+    let mut results = report::CheckResults::default();
+    let result = run_check(inputs, options, xml_options, warn, error, &mut results);
+
+    // The report is written whether or not the check passed; the outcome is unchanged.
+    if let Some(format) = options.report_format {
+        let content = report::render(format, &results)?;
+        match options.report {
+            Some(path) => fs::write(path, content)?,
+            None => print!("{}", content),
+        }
+    }
+    result
+}
+
+fn run_check(
+    inputs: &[PathBuf],
+    options: &CheckOptions,
+    xml_options: &XmlOptions,
+    warn: u64,
+    error: u64,
+    results: &mut report::CheckResults,
+) -> anyhow::Result<()> {
+    let CheckOptions {
+        agents_file,
+        template,
+        ..
+    } = *options;
+
+    let collection = collect_entities_with_diagnostics(inputs, warn, error, options.names);
+    results.entities = collection
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            EntityOrHeader::Entity(e) => Some(e.path.clone()),
+            EntityOrHeader::Header(_) => None,
+        })
+        .collect();
+    let diagnostics = &mut results.diagnostics;
+    diagnostics.extend(collection.diagnostics.iter().cloned());
+    let collected = collection.into_result()?;
+    let entities = options.environment.apply(collected.clone());
+
+    let mut targets: Vec<(PathBuf, Vec<EntityOrHeader>)> = options
+        .config
+        .profiles
+        .iter()
+        .map(|(name, profile)| {
+            (
+                profile.output(name),
+                profile.tag_filter().apply(entities.clone()),
+            )
+        })
+        .collect();
+    targets.insert(0, (agents_file.to_path_buf(), entities));
+    let kept: Vec<Diagnostic> = targets
+        .iter()
+        .flat_map(|(_, items)| check_kept_requirements(items, &collected, inputs))
+        .collect();
+    diagnostics.extend(kept.iter().cloned());
+    fail_on_errors(&kept)?;
 
     let root_header = read_root_header();
-    let expected_xml = generate_xml(&entities, inputs, root_header.as_deref())?;
+    let mut result = Ok(());
+    for (agents_file, items) in &targets {
+        let expected_xml = render_agents_file(
+            items,
+            inputs,
+            root_header.as_deref(),
+            template,
+            xml_options,
+            agents_file,
+        )?;
 
-    validate_token_count("AGENTS.md", &expected_xml, warn, error)?;
+        let label = agents_file.display().to_string();
+        let token_diagnostic = token_count_diagnostic(&label, &expected_xml, warn, error)
+            .map(|d| d.at(agents_file, None));
+        diagnostics.extend(token_diagnostic.clone());
+        enforce_token_count(token_diagnostic)?;
+
+        // Keep going so every stale file is reported; the first failure is returned.
+        if let Err(e) = compare_agents_file(agents_file, &expected_xml, options, diagnostics) {
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+/// Compares the agents file on disk with the expected content, printing the differences.
+fn compare_agents_file(
+    agents_file: &Path,
+    expected_xml: &str,
+    options: &CheckOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> anyhow::Result<()> {
+    let CheckOptions {
+        show_diff,
+        semantic,
+        ..
+    } = *options;
 
     if !agents_file.exists() {
-        anyhow::bail!(
+        let message = format!(
             "{} is missing. Run 'persona build' to generate it.",
             agents_file.display()
         );
+        diagnostics.push(Diagnostic::error("agents-file-missing", &message).at(agents_file, None));
+        return Err(OutOfDate(message).into());
     }
 
     let current_content = fs::read_to_string(agents_file)?;
-    if current_content != expected_xml {
-        tracing::debug!("Expected XML:\n{}", expected_xml);
-        tracing::debug!("Current Content:\n{}", current_content);
-        anyhow::bail!(
+    if semantic {
+        let differences = match semantic_differences(&current_content, expected_xml, agents_file) {
+            Ok(differences) => differences,
+            Err(e) => {
+                diagnostics.push(
+                    Diagnostic::error("agents-file-stale", e.to_string()).at(agents_file, None),
+                );
+                return Err(e);
+            }
+        };
+        if !differences.is_empty() {
+            // Grouped by the entity each difference is in
+            let mut by_entity: BTreeMap<Option<&str>, Vec<&XmlDifference>> = BTreeMap::new();
+            for difference in &differences {
+                by_entity
+                    .entry(difference.entity.as_deref())
+                    .or_default()
+                    .push(difference);
+            }
+            let mut report = String::new();
+            for (entity, differences) in &by_entity {
+                let _ = writeln!(report, "{}:", entity.unwrap_or("(categories)"));
+                for difference in differences {
+                    let _ = writeln!(report, "  {}", difference.detail());
+                    diagnostics.push(
+                        Diagnostic::error("agents-file-stale", difference.to_string())
+                            .at(agents_file, None),
+                    );
+                }
+            }
+            if show_diff {
+                print!("{}", report);
+            } else {
+                eprint!("{}", report);
+            }
+            return Err(OutOfDate(format!(
+                "{} is out of date ({} logical differences). Run 'persona build' to update it.",
+                agents_file.display(),
+                differences.len()
+            ))
+            .into());
+        }
+    } else if current_content != expected_xml {
+        let name = agents_file.display().to_string();
+        if show_diff {
+            print!(
+                "{}",
+                diff::unified_diff(&current_content, expected_xml, &name, false)
+            );
+        } else {
+            eprint!(
+                "{}",
+                diff::unified_diff(&current_content, expected_xml, &name, stderr_color())
+            );
+        }
+        let message = format!(
             "{} is out of date. Run 'persona build' to update it.",
             agents_file.display()
         );
+        diagnostics.push(Diagnostic::error("agents-file-stale", &message).at(
+            agents_file,
+            Some(first_difference_line(&current_content, expected_xml)),
+        ));
+        return Err(OutOfDate(message).into());
     }
 
     Ok(())
 }
 
+/// 1-based number of the first line where `current` and `expected` differ.
+fn first_difference_line(current: &str, expected: &str) -> usize {
+    let mut current_lines = current.split('\n');
+    let mut expected_lines = expected.split('\n');
+    let mut line = 1;
+    while current_lines.next() == expected_lines.next() {
+        line += 1;
+    }
+    line
+}
+
+/// What `persona build` renders and where.
+#[derive(Debug)]
+struct BuildOptions<'a> {
+    /// Directory receiving a copy of the organized inputs.
+    output: Option<&'a Path>,
+    /// Written instead of `AGENTS.md` (or `AGENTS.<persona>.md`); required when `tags` or
+    /// `filter` select a subset, so files `check` compares always hold every entity.
+    agents_file: Option<&'a Path>,
+    template: Option<&'a Path>,
+    filter: Option<&'a Filter>,
+    tags: TagFilter,
+    /// Render only this persona and its skills, into `AGENTS.<persona>.md`.
+    persona: Option<&'a str>,
+    /// Profiles are written next to `AGENTS.md` unless a persona is built.
+    config: &'a Config,
+    /// Entities whose `when` conditions do not hold are left out.
+    environment: &'a Environment,
+    names: DuplicateNames,
+    xml_options: &'a XmlOptions,
+}
+
 #[tracing::instrument]
 fn handle_build_command(
     inputs: &[PathBuf],
-    output: Option<&std::path::Path>,
+    options: &BuildOptions,
     warn: u64,
     error: u64,
 ) -> anyhow::Result<()> {
-    let entities = collect_entities(inputs, warn, error)?;
+    let collected =
+        collect_entities_with_diagnostics(inputs, warn, error, options.names).into_result()?;
+    // Directories of every definition, so that copying a kept entity leaves out the
+    // entities and categories nested in it.
+    let definition_dirs: HashSet<PathBuf> = collected
+        .iter()
+        .filter_map(|item| item.path().parent())
+        .map(Path::to_path_buf)
+        .collect();
+    let entities = options.environment.apply(collected.clone());
     let root_header = read_root_header();
-    let xml_content = generate_xml(&entities, inputs, root_header.as_deref())?;
 
-    validate_token_count("AGENTS.md", &xml_content, warn, error)?;
+    let mut selected = options.tags.apply(entities.clone());
+    if let Some(filter) = options.filter {
+        selected = filter.apply(selected, inputs);
+    }
+    let subset = !options.tags.is_empty() || options.filter.is_some();
+    let agents_file = match options.persona {
+        Some(persona) => {
+            let name = find_entity_of_kind(&selected, inputs, persona, Some(PERSONA_KIND))?
+                .frontmatter
+                .name
+                .clone();
+            selected = bundle(selected, inputs, persona)?;
+            options.agents_file.map_or_else(
+                || PathBuf::from(format!("AGENTS.{}.md", name)),
+                Path::to_path_buf,
+            )
+        }
+        None if subset => {
+            let Some(file) = options.agents_file else {
+                anyhow::bail!(
+                    "--filter, --tag and --exclude-tag build a subset of the entities; \
+                     name the file to write it to with --agents-file"
+                );
+            };
+            let checked = std::iter::once(PathBuf::from("AGENTS.md")).chain(
+                options
+                    .config
+                    .profiles
+                    .iter()
+                    .map(|(name, profile)| profile.output(name)),
+            );
+            if checked.into_iter().any(|target| same_path(&target, file)) {
+                anyhow::bail!(
+                    "'persona check' compares {} with its full set of entities; write the \
+                     subset to another file or configure a profile",
+                    file.display()
+                );
+            }
+            file.to_path_buf()
+        }
+        None => options
+            .agents_file
+            .map_or_else(|| PathBuf::from("AGENTS.md"), Path::to_path_buf),
+    };
+    let write = |items: &[EntityOrHeader], agents_file: &Path| -> anyhow::Result<()> {
+        let xml_content = render_agents_file(
+            items,
+            inputs,
+            root_header.as_deref(),
+            options.template,
+            options.xml_options,
+            agents_file,
+        )?;
+        let label = agents_file.display().to_string();
+        validate_token_count(&label, &xml_content, warn, error)?;
+        if let Some(parent) = agents_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(agents_file, xml_content)?;
+        tracing::info!("Generated {}", label);
+        Ok(())
+    };
 
-    fs::write("AGENTS.md", xml_content)?;
-    tracing::info!("Generated AGENTS.md");
+    // Only configured profiles produce subsets next to the full agents file
+    let profiles: Vec<(PathBuf, Vec<EntityOrHeader>)> = if options.persona.is_none() && !subset {
+        options
+            .config
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                (
+                    profile.output(name),
+                    profile.tag_filter().apply(entities.clone()),
+                )
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut diagnostics = check_kept_requirements(&selected, &collected, inputs);
+    for (_, items) in &profiles {
+        diagnostics.extend(check_kept_requirements(items, &collected, inputs));
+    }
+    fail_on_errors(&diagnostics)?;
+
+    write(&selected, &agents_file)?;
+    for (output, items) in &profiles {
+        write(items, output)?;
+    }
 
-    if let Some(out_dir) = output {
+    if let Some(out_dir) = options.output {
         fs::create_dir_all(out_dir)?;
 
-        for item in entities {
+        // Copy in output order so later entries consistently win when destinations overlap
+        for item in sort_items(&selected, inputs)? {
             let path = item.path();
             // Determine relative path
             let mut relative_path = None;
@@ -102,22 +678,401 @@ fn handle_build_command(
             }
 
             if let Some(rel) = relative_path {
-                let parent_rel = rel.parent().unwrap_or_else(|| Path::new("."));
-                let dest_dir = out_dir.join(parent_rel);
+                let dest = out_dir.join(rel);
+                match item {
+                    // A header stands for its category, not for the entities below it
+                    EntityOrHeader::Header(_) => {
+                        if let Some(parent) = dest.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        tracing::debug!("Copying {} to {}", path.display(), dest.display());
+                        fs::copy(path, &dest)?;
+                    }
+                    EntityOrHeader::Entity(_) => {
+                        let src_dir = path
+                            .parent()
+                            .ok_or_else(|| anyhow::anyhow!("Entity has no parent dir"))?;
+                        let dest_dir = dest.parent().unwrap_or(out_dir);
+                        tracing::debug!("Copying {} to {}", src_dir.display(), dest_dir.display());
+                        copy_dir_recursive(src_dir, dest_dir, &definition_dirs)?;
+                    }
+                }
+            }
+        }
+        tracing::info!("Generated output in {}", out_dir.display());
+    }
+    Ok(())
+}
+
+/// Fails validation when any of `diagnostics` is an error, logging each of them.
+fn fail_on_errors(diagnostics: &[Diagnostic]) -> anyhow::Result<()> {
+    let errors: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    tracing::error!("Validation failed with {} errors:", errors.len());
+    for err in &errors {
+        tracing::error!("- {}", err);
+    }
+    Err(PersonaError::Validation {
+        rules: errors.iter().map(|d| d.rule).collect(),
+    }
+    .into())
+}
+
+/// Whether two relative paths name the same file, ignoring `.` components.
+fn same_path(a: &Path, b: &Path) -> bool {
+    let a = a.components().filter(|c| *c != Component::CurDir);
+    let b = b.components().filter(|c| *c != Component::CurDir);
+    a.eq(b)
+}
 
-                let src_dir = path
-                    .parent()
-                    .ok_or_else(|| anyhow::anyhow!("Entity has no parent dir"))?;
+/// Scaffolds a new entity in the first input directory.
+#[tracing::instrument]
+fn handle_new_command(inputs: &[PathBuf], kind: &str, path: &str) -> anyhow::Result<()> {
+    let input = inputs
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No input directory given"))?;
+    let file = new_entity(input, kind, path)?;
+    println!("Created {}", file.display());
+    Ok(())
+}
+
+/// Prints the details of one entity. With `render`, the entity is also rendered the way
+/// `build` would, with its body inlined.
+#[tracing::instrument]
+fn handle_show_command(
+    inputs: &[PathBuf],
+    query: &str,
+    json: bool,
+    render: Option<&XmlOptions>,
+    names: DuplicateNames,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let entities = collect_entities_with_diagnostics(inputs, warn, error, names).into_result()?;
+    let entity = find_entity(&entities, inputs, query)?;
+    let details = EntityDetails::new(entity, inputs)?;
+
+    let rendered = match render {
+        Some(xml_options) => {
+            let item = entities
+                .iter()
+                .find(|item| item.path() == entity.path)
+                .ok_or_else(|| anyhow::anyhow!("Entity disappeared while rendering"))?;
+            let options = XmlOptions {
+                inline_max_tokens: Some(u64::MAX),
+                base_dir: Some(PathBuf::from(".")),
+                ..xml_options.clone()
+            };
+            Some(generate_xml_with_options(
+                std::slice::from_ref(item),
+                inputs,
+                None,
+                &options,
+            )?)
+        }
+        None => None,
+    };
+
+    if json {
+        let mut value = serde_json::to_value(&details)?;
+        if let Some(rendered) = rendered {
+            value["rendered"] = rendered.into();
+        }
+        println!("{}", serde_json::to_string_pretty(&value)?);
+        return Ok(());
+    }
+
+    println!("name: {}", details.name);
+    println!("kind: {}", details.kind);
+    println!("path: {}", details.path.display());
+    if let Some(input) = &details.input {
+        println!("input: {}", input.display());
+    }
+    println!("category: {}", details.category.join("/"));
+    println!(
+        "tokens: {} (body {})",
+        details.file_tokens, details.body_tokens
+    );
+    println!("assets:");
+    for asset in &details.assets {
+        println!("  {}", asset.display());
+    }
+    println!("frontmatter:");
+    for line in serde_yaml::to_string(&details.frontmatter)?.lines() {
+        println!("  {}", line);
+    }
+    println!("body:");
+    for line in details.body.lines() {
+        println!("  {}", line);
+    }
+    if let Some(rendered) = rendered {
+        println!("rendered:");
+        for line in rendered.lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument]
+fn handle_search_command(
+    inputs: &[PathBuf],
+    query: &str,
+    limit: usize,
+    cache: Option<&Path>,
+    names: DuplicateNames,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let query = SearchQuery::parse(query)?;
+    let collection = collect_entities_with_diagnostics(inputs, warn, error, names);
+    let origins = collection.origins.clone();
+    let entities = collection.into_result()?;
+    let index = match cache {
+        Some(cache) => SearchIndex::cached(&entities, &origins, inputs, cache)?,
+        None => SearchIndex::build(&entities, &origins, inputs),
+    };
+
+    let hits = index.search(&query, limit);
+    if hits.is_empty() {
+        println!("No matches");
+        return Ok(());
+    }
+
+    let color = stdout_color();
+    for (rank, hit) in hits.iter().enumerate() {
+        println!(
+            "{}. {} ({}) {} [{:.2}]",
+            rank + 1,
+            hit.name,
+            hit.kind,
+            hit.path.display(),
+            hit.score
+        );
+        println!("   {}", highlight(&hit.snippet, color));
+    }
+    Ok(())
+}
+
+#[tracing::instrument]
+fn handle_query_command(
+    inputs: &[PathBuf],
+    expr: &str,
+    names: DuplicateNames,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let filter = Filter::parse(expr)?;
+    let entities = collect_entities_with_diagnostics(inputs, warn, error, names).into_result()?;
+    for item in filter.apply(entities, inputs) {
+        if let EntityOrHeader::Entity(entity) = item {
+            println!(
+                "{} ({}) {}",
+                entity.frontmatter.name,
+                entity.kind(),
+                entity.path.display()
+            );
+        }
+    }
+    Ok(())
+}
 
-                copy_dir_recursive(src_dir, &dest_dir)?;
+#[tracing::instrument]
+fn handle_graph_command(
+    inputs: &[PathBuf],
+    format: GraphFormat,
+    focus: Option<&str>,
+    orphans: bool,
+    names: DuplicateNames,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let entities = collect_entities_with_diagnostics(inputs, warn, error, names).into_result()?;
+    let graph = Graph::build(&entities, inputs, focus)?;
+    match format {
+        GraphFormat::Dot => print!("{}", graph.to_dot(orphans)),
+        GraphFormat::Mermaid => print!("{}", graph.to_mermaid(orphans)),
+    }
+    Ok(())
+}
+
+/// Renders a snippet with its matches in bold yellow, or unchanged without colour.
+fn highlight(snippet: &Snippet, color: bool) -> String {
+    if !color {
+        return snippet.text.clone();
+    }
+    let style = anstyle::AnsiColor::Yellow.on_default().bold();
+    let mut out = String::new();
+    let mut last = 0;
+    for range in &snippet.highlights {
+        out.push_str(&snippet.text[last..range.start]);
+        out.push_str(&format!(
+            "{}{}{}",
+            style.render(),
+            &snippet.text[range.clone()],
+            style.render_reset()
+        ));
+        last = range.end;
+    }
+    out.push_str(&snippet.text[last..]);
+    out
+}
+
+/// Moves an entity, rewrites references to it and rebuilds `AGENTS.md` if it exists.
+#[tracing::instrument]
+fn handle_mv_command(
+    inputs: &[PathBuf],
+    entity: &str,
+    destination: &str,
+    dry_run: bool,
+    rebuild: &BuildOptions,
+    warn: u64,
+    error: u64,
+) -> anyhow::Result<()> {
+    let entities =
+        collect_entities_with_diagnostics(inputs, warn, error, rebuild.names).into_result()?;
+    let plan = plan_move(&entities, inputs, entity, destination, rebuild.names)?;
+    let (moving, updating, rebuilding) = if dry_run {
+        ("Would move", "Would update", "Would rebuild")
+    } else {
+        ("Moved", "Updated", "Rebuilt")
+    };
+
+    if !dry_run {
+        plan.apply()?;
+    }
+    println!(
+        "{} {} -> {}",
+        moving,
+        plan.from_dir.display(),
+        plan.to_dir.display()
+    );
+    for edit in &plan.edits {
+        println!(
+            "{} {} ({} references)",
+            updating,
+            edit.path.display(),
+            edit.replacements
+        );
+        if dry_run {
+            for (old, new) in edit.original.lines().zip(edit.updated.lines()) {
+                if old != new {
+                    println!("  - {}", old);
+                    println!("  + {}", new);
+                }
             }
         }
-        tracing::info!("Generated output in {}", out_dir.display());
+    }
+
+    // A plain build writes the agents file and every configured profile
+    let agents_file = PathBuf::from("AGENTS.md");
+    if agents_file.exists() {
+        if !dry_run {
+            handle_build_command(inputs, rebuild, warn, error)?;
+        }
+        let profiles = rebuild
+            .config
+            .profiles
+            .iter()
+            .map(|(name, profile)| profile.output(name));
+        for target in std::iter::once(agents_file).chain(profiles) {
+            println!("{} {}", rebuilding, target.display());
+        }
     }
     Ok(())
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
+/// Renders the agents file, either as the built-in XML or through a user template.
+/// Paths are emitted relative to the directory `output_file` is written to.
+fn render_agents_file(
+    entities: &[EntityOrHeader],
+    inputs: &[PathBuf],
+    root_header: Option<&str>,
+    template: Option<&Path>,
+    xml_options: &XmlOptions,
+    output_file: &Path,
+) -> anyhow::Result<String> {
+    let base_dir = output_file.parent().unwrap_or(Path::new("."));
+    match template {
+        Some(template) => {
+            let template_path = resolve_template(template, inputs)?;
+            let source = fs::read_to_string(&template_path)?;
+            let name = template_path.to_string_lossy();
+            Ok(render_template(
+                entities,
+                inputs,
+                root_header,
+                base_dir,
+                &name,
+                &source,
+            )?)
+        }
+        None => {
+            let options = XmlOptions {
+                base_dir: Some(base_dir.to_path_buf()),
+                ..xml_options.clone()
+            };
+            Ok(generate_xml_with_options(
+                entities,
+                inputs,
+                root_header,
+                &options,
+            )?)
+        }
+    }
+}
+
+/// Looks up a template relative to the working directory first, then inside each input.
+fn resolve_template(template: &Path, inputs: &[PathBuf]) -> anyhow::Result<PathBuf> {
+    if template.exists() {
+        return Ok(template.to_path_buf());
+    }
+    if template.is_relative() {
+        for input in inputs {
+            let candidate = input.join(template);
+            if candidate.exists() {
+                return Ok(candidate);
+            }
+        }
+    }
+    anyhow::bail!("Template '{}' does not exist", template.display())
+}
+
+/// Parses both files as XML and lists their logical differences.
+fn semantic_differences(
+    current: &str,
+    expected: &str,
+    agents_file: &Path,
+) -> anyhow::Result<Vec<XmlDifference>> {
+    let expected = parse_xml(expected)?;
+    let current = parse_xml(current).map_err(|e| {
+        OutOfDate(format!(
+            "{} is not valid XML ({}). Run 'persona build' to regenerate it.",
+            agents_file.display(),
+            e
+        ))
+    })?;
+    Ok(compare_xml(&current, &expected))
+}
+
+/// Colour stderr output on terminals and in CI logs, unless `NO_COLOR` is set.
+fn stderr_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none()
+        && (std::io::stderr().is_terminal() || std::env::var_os("CI").is_some())
+}
+
+/// Colour stdout output on terminals, unless `NO_COLOR` is set.
+fn stdout_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+/// Copies `src` into `dst`, skipping the subdirectories in `skip`.
+fn copy_dir_recursive(src: &Path, dst: &Path, skip: &HashSet<PathBuf>) -> std::io::Result<()> {
     if !dst.exists() {
         fs::create_dir_all(dst)?;
     }
@@ -129,7 +1084,9 @@ fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
         let dest_path = dst.join(entry.file_name());
 
         if ty.is_dir() {
-            copy_dir_recursive(&path, &dest_path)?;
+            if !skip.contains(&path) {
+                copy_dir_recursive(&path, &dest_path, skip)?;
+            }
         } else {
             fs::copy(&path, &dest_path)?;
         }
@@ -141,7 +1098,13 @@ fn read_root_header() -> Option<String> {
     let header_path = Path::new(".agent/HEADER.md");
     if header_path.exists() {
         match fs::read_to_string(header_path) {
-            Ok(content) => Some(content),
+            Ok(content) => match Header::parse(header_path.to_path_buf(), content) {
+                Ok(header) => Some(header.body),
+                Err(e) => {
+                    tracing::warn!("Failed to parse .agent/HEADER.md: {}", e);
+                    None
+                }
+            },
             Err(e) => {
                 tracing::warn!("Failed to read .agent/HEADER.md: {}", e);
                 None
@@ -153,23 +1116,26 @@ fn read_root_header() -> Option<String> {
 }
 
 fn validate_token_count(name: &str, content: &str, warn: u64, error: u64) -> anyhow::Result<()> {
+    enforce_token_count(token_count_diagnostic(name, content, warn, error))
+}
+
+fn token_count_diagnostic(name: &str, content: &str, warn: u64, error: u64) -> Option<Diagnostic> {
     let count = content.chars().count();
     let tokens = (count / 5) as u64;
+    Diagnostic::token_budget(name, tokens, warn, error)
+}
 
-    if tokens > error {
-        let msg = format!(
-            "{} exceeds error limit of {} tokens (has {})",
-            name, error, tokens
-        );
-        tracing::error!("{}", msg);
-        anyhow::bail!(msg);
-    } else if tokens > warn {
-        tracing::warn!(
-            "{} exceeds warning limit of {} tokens (has {})",
-            name,
-            warn,
-            tokens
-        );
+/// Logs a token budget diagnostic, failing when the error limit was exceeded.
+fn enforce_token_count(diagnostic: Option<Diagnostic>) -> anyhow::Result<()> {
+    match diagnostic {
+        Some(d) if d.severity == Severity::Error => {
+            tracing::error!("{}", d.message);
+            Err(PersonaError::TokenBudget(d.message).into())
+        }
+        Some(d) => {
+            tracing::warn!("{}", d.message);
+            Ok(())
+        }
+        None => Ok(()),
     }
-    Ok(())
 }
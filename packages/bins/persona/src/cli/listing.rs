@@ -0,0 +1,353 @@
+use anstyle::{AnsiColor, Style};
+use persona_core::{HierarchyRow, ParsedEntity, layers::Origin};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::cli::ListFormat;
+
+const CATEGORY: Style = AnsiColor::Blue.on_default().bold();
+const DIM: Style = Style::new().dimmed();
+
+/// What `persona list` prints: the category tree of the listed entities and, with
+/// `--explain`, the entities left out with the reasons.
+#[derive(Debug)]
+pub struct Listing<'a> {
+    pub rows: Vec<HierarchyRow<'a>>,
+    pub origins: &'a HashMap<PathBuf, Origin>,
+    pub excluded: Option<Vec<(&'a ParsedEntity, Vec<String>)>>,
+}
+
+/// How much of each entity to show and how.
+#[derive(Debug, Default)]
+pub struct Columns {
+    /// Kind, token count, path and description after the name.
+    pub long: bool,
+    /// The inputs each entity came from.
+    pub origin: bool,
+    pub color: bool,
+    /// Descriptions are cut to fit lines of this many characters.
+    pub width: Option<usize>,
+}
+
+pub fn render(format: ListFormat, listing: &Listing, columns: &Columns) -> anyhow::Result<String> {
+    let mut out = match format {
+        ListFormat::Tree => tree(listing, columns),
+        ListFormat::Flat => flat(listing, columns),
+        ListFormat::Json => return Ok(serde_json::to_string_pretty(&json_listing(listing))? + "\n"),
+    };
+    if let Some(excluded) = listing.excluded.as_ref().filter(|e| !e.is_empty()) {
+        out.push_str("\nExcluded:\n");
+        for (entity, reasons) in excluded {
+            let _ = writeln!(
+                out,
+                "  {} ({}): {}",
+                entity.frontmatter.name,
+                entity.path.display(),
+                reasons.join("; ")
+            );
+        }
+    }
+    Ok(out)
+}
+
+/// Width of the terminal in columns: `COLUMNS` when set, otherwise the size of the
+/// terminal on stdout, defaulting to 80.
+pub fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|&columns| columns > 0)
+        .or_else(|| terminal_size::terminal_size().map(|(width, _)| usize::from(width.0)))
+        .filter(|&columns| columns > 0)
+        .unwrap_or(80)
+}
+
+/// The path of a row with forward slashes, e.g. `skills/vcs/git`.
+fn id(row: &HierarchyRow) -> String {
+    row.path.to_string_lossy().replace('\\', "/")
+}
+
+/// The entity file with forward slashes.
+fn file(entity: &ParsedEntity) -> String {
+    entity.path.to_string_lossy().replace('\\', "/")
+}
+
+/// The description on one line, cut with an ellipsis to at most `max` characters.
+fn description(entity: &ParsedEntity, max: Option<usize>) -> String {
+    let text = entity
+        .frontmatter
+        .description
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    match max {
+        Some(max) if text.chars().count() > max => {
+            let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+            format!("{}…", cut.trim_end())
+        }
+        _ => text,
+    }
+}
+
+fn paint(color: bool, style: Style, text: &str) -> String {
+    if color {
+        format!("{}{}{}", style.render(), text, style.render_reset())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Entity names indented by depth, categories marked with a trailing `/`. With `--long`
+/// the columns after the name are aligned across the whole tree.
+fn tree(listing: &Listing, columns: &Columns) -> String {
+    let label = |row: &HierarchyRow, entity: &ParsedEntity| {
+        let mut label = format!("{:indent$}{}", "", row.name, indent = row.depth * 2);
+        if columns.origin
+            && !columns.long
+            && let Some(origin) = listing.origins.get(&entity.path)
+        {
+            let _ = write!(label, " (from {})", origin);
+        }
+        label
+    };
+    let cells = |entity: &ParsedEntity| {
+        let mut cells = vec![
+            entity.kind(),
+            entity.body_tokens().to_string(),
+            file(entity),
+        ];
+        if columns.origin {
+            let origin = listing.origins.get(&entity.path);
+            cells.push(origin.map_or_else(String::new, |o| format!("from {}", o)));
+        }
+        cells
+    };
+
+    let entities: Vec<(String, Vec<String>)> = listing
+        .rows
+        .iter()
+        .filter_map(|row| row.entity.map(|entity| (label(row, entity), cells(entity))))
+        .collect();
+    let mut widths = [0; 5];
+    for (label, cells) in &entities {
+        widths[0] = widths[0].max(label.chars().count());
+        for (i, cell) in cells.iter().enumerate() {
+            widths[i + 1] = widths[i + 1].max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let mut entities = entities.into_iter();
+    for row in &listing.rows {
+        let Some(entity) = row.entity else {
+            let name = format!("{}/", row.name);
+            let _ = writeln!(
+                out,
+                "{:indent$}{}",
+                "",
+                paint(columns.color, CATEGORY, &name),
+                indent = row.depth * 2
+            );
+            continue;
+        };
+        let (label, cells) = entities.next().unwrap_or_default();
+        if !columns.long {
+            let _ = writeln!(out, "{}", label);
+            continue;
+        }
+
+        let mut line = format!("{:width$}", label, width = widths[0]);
+        for (i, cell) in cells.iter().enumerate() {
+            let cell = match i {
+                1 => format!("{:>width$}", cell, width = widths[i + 1]),
+                _ => format!("{:width$}", cell, width = widths[i + 1]),
+            };
+            let _ = write!(line, "  {}", cell);
+        }
+        let used = line.chars().count() + 2;
+        let description = description(
+            entity,
+            columns
+                .width
+                .map(|width| width.saturating_sub(used).max(10)),
+        );
+        let _ = writeln!(
+            out,
+            "{}  {}",
+            paint(columns.color, DIM, line.trim_end()),
+            description
+        );
+    }
+    out
+}
+
+/// One entity per line by its path in the tree; with `--long`, tab-separated columns for
+/// scripts: path, kind, tokens, file, origin (with `--origin`) and description.
+fn flat(listing: &Listing, columns: &Columns) -> String {
+    let mut out = String::new();
+    for row in &listing.rows {
+        let Some(entity) = row.entity else {
+            continue;
+        };
+        let origin = listing.origins.get(&entity.path).filter(|_| columns.origin);
+        if !columns.long {
+            match origin {
+                Some(origin) => {
+                    let _ = writeln!(out, "{} (from {})", id(row), origin);
+                }
+                None => {
+                    let _ = writeln!(out, "{}", id(row));
+                }
+            }
+            continue;
+        }
+        let mut fields = vec![
+            id(row),
+            entity.kind(),
+            entity.body_tokens().to_string(),
+            file(entity),
+        ];
+        if columns.origin {
+            fields.push(origin.map_or_else(String::new, ToString::to_string));
+        }
+        fields.push(description(entity, None));
+        let _ = writeln!(out, "{}", fields.join("\t"));
+    }
+    out
+}
+
+/// Every field of every entity, whatever `--long` and `--origin` say.
+fn json_listing(listing: &Listing) -> Value {
+    let entities: Vec<Value> = listing
+        .rows
+        .iter()
+        .filter_map(|row| {
+            let entity = row.entity?;
+            let category = row
+                .path
+                .parent()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default();
+            Some(json!({
+                "id": id(row),
+                "name": entity.frontmatter.name,
+                "kind": entity.kind(),
+                "category": category,
+                "path": file(entity),
+                "tokens": entity.body_tokens(),
+                "description": entity.frontmatter.description.trim(),
+                "origin": listing.origins.get(&entity.path).map(ToString::to_string),
+            }))
+        })
+        .collect();
+    let mut value = json!({ "entities": entities });
+    if let Some(excluded) = &listing.excluded {
+        value["excluded"] = excluded
+            .iter()
+            .map(|(entity, reasons)| {
+                json!({
+                    "name": entity.frontmatter.name,
+                    "path": file(entity),
+                    "reasons": reasons,
+                })
+            })
+            .collect();
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_core::{EntityOrHeader, Frontmatter, hierarchy};
+    use std::path::Path;
+
+    fn entity(path: &str, description: &str) -> EntityOrHeader {
+        let name = Path::new(path)
+            .parent()
+            .and_then(|p| p.file_name())
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name,
+                description: description.to_string(),
+                ..Default::default()
+            },
+            body: "b".repeat(50),
+            char_count: 50,
+        })
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity("/in/skills/vcs/git/SKILL.md", "Version control with git"),
+            entity("/in/skills/rust/SKILL.md", "Rust\n  conventions"),
+        ]
+    }
+
+    fn render_with(format: ListFormat, columns: &Columns) -> String {
+        let items = items();
+        let origins = HashMap::new();
+        let listing = Listing {
+            rows: hierarchy(&items, &[PathBuf::from("/in")]),
+            origins: &origins,
+            excluded: None,
+        };
+        render(format, &listing, columns).unwrap()
+    }
+
+    #[test]
+    fn test_tree_listing() {
+        assert_eq!(
+            render_with(ListFormat::Tree, &Columns::default()),
+            "skills/\n  rust\n  vcs/\n    git\n"
+        );
+        let long = render_with(
+            ListFormat::Tree,
+            &Columns {
+                long: true,
+                width: Some(65),
+                ..Default::default()
+            },
+        );
+        let lines: Vec<&str> = long.lines().collect();
+        assert!(lines[1].starts_with("  rust   skill  10  /in/skills/rust/SKILL.md"));
+        assert!(lines[1].ends_with("Rust conventions"));
+        assert!(lines[3].starts_with("    git  skill  10  /in/skills/vcs/git/SKILL.md"));
+        assert!(lines[3].ends_with("Version control…"));
+    }
+
+    #[test]
+    fn test_flat_listing() {
+        assert_eq!(
+            render_with(ListFormat::Flat, &Columns::default()),
+            "skills/rust\nskills/vcs/git\n"
+        );
+        let long = render_with(
+            ListFormat::Flat,
+            &Columns {
+                long: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            long.lines().next(),
+            Some("skills/rust\tskill\t10\t/in/skills/rust/SKILL.md\tRust conventions")
+        );
+    }
+
+    #[test]
+    fn test_json_listing() {
+        let value: Value =
+            serde_json::from_str(&render_with(ListFormat::Json, &Columns::default())).unwrap();
+        assert_eq!(value["entities"][1]["id"], "skills/vcs/git");
+        assert_eq!(value["entities"][1]["category"], "skills/vcs");
+        assert_eq!(value["entities"][1]["tokens"], 10);
+        assert!(value.get("excluded").is_none());
+    }
+}
@@ -1,6 +1,15 @@
+mod completions;
+mod diff;
+pub mod error;
 pub mod handlers;
-
-use clap::{Parser, Subcommand};
+mod listing;
+mod report;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::ArgValueCandidates;
+use persona_core::conditions::Assumption;
+use persona_core::names::DuplicateNames;
+use persona_core::xml::EscapeMode;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -23,23 +32,243 @@ pub struct Cli {
     #[arg(long, global = true, default_value = "10000")]
     pub error_token_count: u64,
 
+    /// How text is written into AGENTS.md
+    #[arg(long, global = true, value_enum, default_value_t = XmlEscape::Escape)]
+    pub xml_escape: XmlEscape,
+
+    /// Inline entity bodies of at most this many tokens into AGENTS.md
+    #[arg(long, global = true)]
+    pub inline_max_tokens: Option<u64>,
+
+    /// Configuration file with build profiles (default: persona.yaml when present)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// How entities sharing a name are reported (default: from the configuration, else warn)
+    #[arg(long, global = true, value_enum)]
+    pub duplicate_names: Option<NamePolicy>,
+
+    /// Treat a `when` condition as met, or not with `=false`, e.g. `binary:jj` (repeatable)
+    #[arg(long, global = true, value_name = "KIND:VALUE[=BOOL]")]
+    pub assume: Vec<Assumption>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmlEscape {
+    /// Escape XML special characters
+    Escape,
+    /// Wrap text in CDATA sections
+    Cdata,
+    /// Write text verbatim (compatibility with older outputs; may produce invalid XML)
+    Raw,
+}
+
+impl From<XmlEscape> for EscapeMode {
+    fn from(value: XmlEscape) -> Self {
+        match value {
+            XmlEscape::Escape => EscapeMode::Escape,
+            XmlEscape::Cdata => EscapeMode::Cdata,
+            XmlEscape::Raw => EscapeMode::Raw,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// Entities sharing a name fail validation
+    Error,
+    /// Entities sharing a name are reported as warnings
+    Warn,
+    /// Entities of different kinds may share a name
+    AllowNamespaced,
+}
+
+impl From<NamePolicy> for DuplicateNames {
+    fn from(value: NamePolicy) -> Self {
+        match value {
+            NamePolicy::Error => DuplicateNames::Error,
+            NamePolicy::Warn => DuplicateNames::Warn,
+            NamePolicy::AllowNamespaced => DuplicateNames::AllowNamespaced,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JSON object with every diagnostic
+    Json,
+    /// SARIF 2.1.0 log for code-scanning tools
+    Sarif,
+    /// GitHub Actions workflow commands for inline annotations
+    Github,
+    /// JUnit XML with one test case per entity
+    Junit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    /// Category tree
+    Tree,
+    /// One entity per line by its path in the tree
+    Flat,
+    /// JSON document with every field of every entity
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
 #[derive(Subcommand, Debug, PartialEq)]
 pub enum Commands {
     #[command(about = "Validate agent skill definitions")]
     Check {
         #[arg(long, default_value = "AGENTS.md")]
         agents_file: PathBuf,
+        /// Jinja template used to render the agents file instead of the built-in XML format
+        #[arg(long)]
+        template: Option<PathBuf>,
+        /// Print a plain unified diff to stdout when the agents file is out of date
+        #[arg(long, conflicts_with = "report_format")]
+        diff: bool,
+        /// Compare XML structure and values, ignoring whitespace and attribute order
+        #[arg(long, conflicts_with = "template")]
+        semantic: bool,
+        /// Emit all diagnostics in a machine-readable format
+        #[arg(long, value_enum)]
+        report_format: Option<ReportFormat>,
+        /// Write the report to this file instead of stdout
+        #[arg(long, requires = "report_format")]
+        report: Option<PathBuf>,
     },
     #[command(about = "List available agent skills")]
-    List,
+    List {
+        /// Only list entities matching this filter expression
+        #[arg(long, add = ArgValueCandidates::new(completions::filters))]
+        filter: Option<String>,
+        /// Only list entities with this tag (repeatable; any tag matches)
+        #[arg(long, add = ArgValueCandidates::new(completions::tags))]
+        tag: Vec<String>,
+        /// Leave out entities with this tag (repeatable)
+        #[arg(long, add = ArgValueCandidates::new(completions::tags))]
+        exclude_tag: Vec<String>,
+        /// Also list the excluded entities and why each was left out
+        #[arg(long)]
+        explain: bool,
+        /// Show which inputs each entity came from
+        #[arg(long)]
+        origin: bool,
+        #[arg(long, value_enum, default_value = "tree")]
+        format: ListFormat,
+        /// Also show the kind, token count, file and description of each entity
+        #[arg(short, long)]
+        long: bool,
+    },
     #[command(about = "Build the agent knowledge summary")]
     Build {
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Jinja template used to render the agents file instead of the built-in XML format
+        #[arg(long)]
+        template: Option<PathBuf>,
+        /// Only include entities matching this filter expression
+        #[arg(long, add = ArgValueCandidates::new(completions::filters))]
+        filter: Option<String>,
+        /// Only include entities with this tag (repeatable; any tag matches)
+        #[arg(long, add = ArgValueCandidates::new(completions::tags))]
+        tag: Vec<String>,
+        /// Leave out entities with this tag (repeatable)
+        #[arg(long, add = ArgValueCandidates::new(completions::tags))]
+        exclude_tag: Vec<String>,
+        /// Write `AGENTS.<PERSONA>.md` with only this persona, its skills and their requirements
+        #[arg(long, add = ArgValueCandidates::new(completions::personas))]
+        persona: Option<String>,
+        /// File to write instead of `AGENTS.md`; required with --filter, --tag and --exclude-tag
+        #[arg(long)]
+        agents_file: Option<PathBuf>,
+    },
+    #[command(about = "Create a new entity from a template")]
+    New {
+        /// Entity kind, e.g. `skill` or `persona`
+        kind: String,
+        /// Category path and name of the entity, e.g. `coding/rust-review`
+        #[arg(add = ArgValueCandidates::new(completions::categories))]
+        path: String,
+    },
+    #[command(about = "Show everything parsed for a single entity")]
+    Show {
+        /// Name or path of the entity
+        #[arg(add = ArgValueCandidates::new(completions::entities))]
+        entity: String,
+        /// Print the details as JSON
+        #[arg(long)]
+        json: bool,
+        /// Also print the entity as it is rendered into AGENTS.md, with its body inlined
+        #[arg(long)]
+        render: bool,
+    },
+    #[command(about = "Search entity names, descriptions and bodies")]
+    Search {
+        /// Search terms, optionally with field filters such as `kind:skill`
+        #[arg(required = true, num_args = 1..)]
+        query: Vec<String>,
+        /// Maximum number of results
+        #[arg(long, default_value = "10")]
+        limit: usize,
+        /// Store the search index in this file and reuse it while the inputs are unchanged
+        #[arg(long)]
+        cache: Option<PathBuf>,
+    },
+    #[command(about = "List entities matching a filter expression")]
+    Query {
+        /// Filter expression, e.g. `kind = skill and compatibility contains jj`
+        expr: String,
+    },
+    #[command(about = "Print the category tree and entity requirements as a graph")]
+    Graph {
+        #[arg(long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+        /// Only show this entity and everything it transitively requires
+        #[arg(long, add = ArgValueCandidates::new(completions::entities))]
+        focus: Option<String>,
+        /// Highlight entities that no other entity requires
+        #[arg(long)]
+        orphans: bool,
+    },
+    #[command(about = "Rename or move an entity and update references to it")]
+    Mv {
+        /// Name or path of the entity to move
+        #[arg(add = ArgValueCandidates::new(completions::entities))]
+        entity: String,
+        /// New category path and name, relative to the kind directory
+        #[arg(add = ArgValueCandidates::new(completions::categories))]
+        destination: String,
+        /// Print the changes without applying them
+        #[arg(long)]
+        dry_run: bool,
+        /// Jinja template used to rebuild the agents file instead of the built-in XML format
+        #[arg(long)]
+        template: Option<PathBuf>,
+    },
+    #[command(about = "Print a shell completion script")]
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
     },
 }
 
@@ -58,7 +287,21 @@ mod tests {
     fn test_check_command_parsing() {
         let cli = Cli::parse_from(["persona", "check"]);
         match cli.command {
-            Commands::Check { agents_file } => assert_eq!(agents_file, PathBuf::from("AGENTS.md")),
+            Commands::Check {
+                agents_file,
+                template,
+                diff,
+                semantic,
+                report_format,
+                report,
+            } => {
+                assert_eq!(agents_file, PathBuf::from("AGENTS.md"));
+                assert!(template.is_none());
+                assert!(!diff);
+                assert!(!semantic);
+                assert!(report_format.is_none());
+                assert!(report.is_none());
+            }
             _ => panic!("Expected Check command"),
         }
     }
@@ -67,16 +310,165 @@ mod tests {
     fn test_list_command_parsing() {
         let cli = Cli::parse_from(["persona", "list"]);
         match cli.command {
-            Commands::List => (),
+            Commands::List {
+                filter,
+                tag,
+                exclude_tag,
+                explain,
+                origin,
+                format,
+                long,
+            } => {
+                assert!(filter.is_none());
+                assert!(tag.is_empty());
+                assert!(exclude_tag.is_empty());
+                assert!(!explain);
+                assert!(!origin);
+                assert_eq!(format, ListFormat::Tree);
+                assert!(!long);
+            }
             _ => panic!("Expected List command"),
         }
     }
 
+    #[test]
+    fn test_filter_arg() {
+        let cli = Cli::parse_from(["persona", "list", "--filter", "kind = skill"]);
+        match cli.command {
+            Commands::List { filter, .. } => assert_eq!(filter.as_deref(), Some("kind = skill")),
+            _ => panic!("Expected List command"),
+        }
+
+        let cli = Cli::parse_from(["persona", "build", "--filter", "exists(license)"]);
+        match cli.command {
+            Commands::Build { filter, .. } => {
+                assert_eq!(filter.as_deref(), Some("exists(license)"))
+            }
+            _ => panic!("Expected Build command"),
+        }
+    }
+
+    #[test]
+    fn test_graph_command_parsing() {
+        let cli = Cli::parse_from(["persona", "graph"]);
+        assert_eq!(
+            cli.command,
+            Commands::Graph {
+                format: GraphFormat::Dot,
+                focus: None,
+                orphans: false,
+            }
+        );
+
+        let cli = Cli::parse_from([
+            "persona",
+            "graph",
+            "--format",
+            "mermaid",
+            "--focus",
+            "dev",
+            "--orphans",
+        ]);
+        assert_eq!(
+            cli.command,
+            Commands::Graph {
+                format: GraphFormat::Mermaid,
+                focus: Some("dev".to_string()),
+                orphans: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_tag_args() {
+        let cli = Cli::parse_from([
+            "persona",
+            "--config",
+            "ci.yaml",
+            "list",
+            "--tag",
+            "backend",
+            "--tag",
+            "shared",
+            "--exclude-tag",
+            "legacy",
+        ]);
+        assert_eq!(cli.config, Some(PathBuf::from("ci.yaml")));
+        match cli.command {
+            Commands::List {
+                tag, exclude_tag, ..
+            } => {
+                assert_eq!(tag, vec!["backend", "shared"]);
+                assert_eq!(exclude_tag, vec!["legacy"]);
+            }
+            _ => panic!("Expected List command"),
+        }
+    }
+
+    #[test]
+    fn test_assume_and_explain_args() {
+        let cli = Cli::parse_from([
+            "persona",
+            "list",
+            "--explain",
+            "--assume",
+            "binary:jj",
+            "--assume",
+            "env:CI=false",
+        ]);
+        assert_eq!(
+            cli.assume,
+            vec![
+                "binary:jj".parse::<Assumption>().unwrap(),
+                "env:CI=false".parse::<Assumption>().unwrap(),
+            ]
+        );
+        assert!(matches!(cli.command, Commands::List { explain: true, .. }));
+
+        let cli = Cli::parse_from(["persona", "list", "--origin"]);
+        assert!(matches!(cli.command, Commands::List { origin: true, .. }));
+        assert!(Cli::try_parse_from(["persona", "--assume", "jj", "list"]).is_err());
+    }
+
+    #[test]
+    fn test_persona_arg() {
+        let cli = Cli::parse_from(["persona", "build", "--persona", "qa"]);
+        match cli.command {
+            Commands::Build { persona, .. } => assert_eq!(persona.as_deref(), Some("qa")),
+            _ => panic!("Expected Build command"),
+        }
+    }
+
+    #[test]
+    fn test_query_command_parsing() {
+        let cli = Cli::parse_from(["persona", "query", "name ~ rust"]);
+        match cli.command {
+            Commands::Query { expr } => assert_eq!(expr, "name ~ rust"),
+            _ => panic!("Expected Query command"),
+        }
+    }
+
     #[test]
     fn test_build_command_parsing() {
         let cli = Cli::parse_from(["persona", "build"]);
         match cli.command {
-            Commands::Build { output } => assert!(output.is_none()),
+            Commands::Build {
+                output,
+                template,
+                filter,
+                tag,
+                exclude_tag,
+                persona,
+                agents_file,
+            } => {
+                assert!(output.is_none());
+                assert!(template.is_none());
+                assert!(filter.is_none());
+                assert!(tag.is_empty());
+                assert!(exclude_tag.is_empty());
+                assert!(persona.is_none());
+                assert!(agents_file.is_none());
+            }
             _ => panic!("Expected Build command"),
         }
     }
@@ -112,11 +504,171 @@ mod tests {
     fn test_build_output_arg() {
         let cli = Cli::parse_from(["persona", "build", "-o", "output_dir"]);
         match cli.command {
-            Commands::Build { output } => assert_eq!(output, Some(PathBuf::from("output_dir"))),
+            Commands::Build { output, .. } => {
+                assert_eq!(output, Some(PathBuf::from("output_dir")))
+            }
             _ => panic!("Expected Build command with output"),
         }
     }
 
+    #[test]
+    fn test_template_arg() {
+        let cli = Cli::parse_from(["persona", "build", "--template", "AGENTS.md.j2"]);
+        match cli.command {
+            Commands::Build { template, .. } => {
+                assert_eq!(template, Some(PathBuf::from("AGENTS.md.j2")))
+            }
+            _ => panic!("Expected Build command with template"),
+        }
+    }
+
+    #[test]
+    fn test_check_diff_arg() {
+        let cli = Cli::parse_from(["persona", "check", "--diff"]);
+        match cli.command {
+            Commands::Check { diff, .. } => assert!(diff),
+            _ => panic!("Expected Check command"),
+        }
+    }
+
+    #[test]
+    fn test_check_semantic_arg() {
+        let cli = Cli::parse_from(["persona", "check", "--semantic"]);
+        match cli.command {
+            Commands::Check { semantic, .. } => assert!(semantic),
+            _ => panic!("Expected Check command"),
+        }
+
+        let result = Cli::try_parse_from(["persona", "check", "--semantic", "--template", "A.j2"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_check_report_args() {
+        let cli = Cli::parse_from([
+            "persona",
+            "check",
+            "--report-format",
+            "sarif",
+            "--report",
+            "persona.sarif",
+        ]);
+        match cli.command {
+            Commands::Check {
+                report_format,
+                report,
+                ..
+            } => {
+                assert_eq!(report_format, Some(ReportFormat::Sarif));
+                assert_eq!(report, Some(PathBuf::from("persona.sarif")));
+            }
+            _ => panic!("Expected Check command"),
+        }
+
+        assert!(Cli::try_parse_from(["persona", "check", "--report", "out.json"]).is_err());
+        assert!(
+            Cli::try_parse_from(["persona", "check", "--report-format", "json", "--diff"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_new_command_parsing() {
+        let cli = Cli::parse_from(["persona", "new", "skill", "coding/rust-review"]);
+        match cli.command {
+            Commands::New { kind, path } => {
+                assert_eq!(kind, "skill");
+                assert_eq!(path, "coding/rust-review");
+            }
+            _ => panic!("Expected New command"),
+        }
+    }
+
+    #[test]
+    fn test_show_command_parsing() {
+        let cli = Cli::parse_from(["persona", "show", "skills/rust", "--json"]);
+        match cli.command {
+            Commands::Show {
+                entity,
+                json,
+                render,
+            } => {
+                assert_eq!(entity, "skills/rust");
+                assert!(json);
+                assert!(!render);
+            }
+            _ => panic!("Expected Show command"),
+        }
+    }
+
+    #[test]
+    fn test_search_command_parsing() {
+        let cli = Cli::parse_from(["persona", "search", "kind:skill", "database", "migrations"]);
+        match cli.command {
+            Commands::Search {
+                query,
+                limit,
+                cache,
+            } => {
+                assert_eq!(query, vec!["kind:skill", "database", "migrations"]);
+                assert_eq!(limit, 10);
+                assert!(cache.is_none());
+            }
+            _ => panic!("Expected Search command"),
+        }
+        assert!(Cli::try_parse_from(["persona", "search"]).is_err());
+    }
+
+    #[test]
+    fn test_mv_command_parsing() {
+        let cli = Cli::parse_from(["persona", "mv", "rust", "tools/rust-lang", "--dry-run"]);
+        match cli.command {
+            Commands::Mv {
+                entity,
+                destination,
+                dry_run,
+                template,
+            } => {
+                assert_eq!(entity, "rust");
+                assert_eq!(destination, "tools/rust-lang");
+                assert!(dry_run);
+                assert_eq!(template, None);
+            }
+            _ => panic!("Expected Mv command"),
+        }
+    }
+
+    #[test]
+    fn test_completions_args() {
+        let cli = Cli::parse_from(["persona", "completions", "nushell"]);
+        assert_eq!(
+            cli.command,
+            Commands::Completions {
+                shell: Shell::Nushell
+            }
+        );
+        assert!(Cli::try_parse_from(["persona", "__complete", "categories"]).is_err());
+        assert!(Cli::try_parse_from(["persona", "completions", "powershell"]).is_err());
+    }
+
+    #[test]
+    fn test_xml_escape_arg() {
+        let cli = Cli::parse_from(["persona", "check"]);
+        assert_eq!(cli.xml_escape, XmlEscape::Escape);
+
+        let cli = Cli::parse_from(["persona", "build", "--xml-escape", "cdata"]);
+        assert_eq!(cli.xml_escape, XmlEscape::Cdata);
+        assert_eq!(EscapeMode::from(cli.xml_escape), EscapeMode::Cdata);
+    }
+
+    #[test]
+    fn test_inline_max_tokens_arg() {
+        let cli = Cli::parse_from(["persona", "check"]);
+        assert_eq!(cli.inline_max_tokens, None);
+
+        let cli = Cli::parse_from(["persona", "--inline-max-tokens", "200", "build"]);
+        assert_eq!(cli.inline_max_tokens, Some(200));
+    }
+
     #[test]
     fn test_token_args_parsing() {
         let cli = Cli::parse_from([
@@ -143,7 +695,8 @@ mod tests {
     // Integration-style tests to cover handlers
     #[test]
     fn test_handle_cli_check() {
-        use persona_core::{collect_entities, xml::generate_xml};
+        use persona_core::collect_entities;
+        use persona_core::xml::{XmlOptions, generate_xml_with_options};
 
         let temp_dir = setup_temp_dir("persona_test_check");
         let inputs_dir = temp_dir.join("inputs");
@@ -157,9 +710,13 @@ mod tests {
         let content = "---\nname: myskill\ndescription: Test skill\n---\nBody";
         std::fs::write(&skill_file, content).unwrap();
 
-        // Generate expected AGENTS.md content dynamically
+        // Generate expected AGENTS.md content dynamically, relative to where it is written
         let entities = collect_entities(&inputs, 5000, 10000).unwrap();
-        let xml_content = generate_xml(&entities, &inputs, None).unwrap();
+        let options = XmlOptions {
+            base_dir: Some(temp_dir.clone()),
+            ..Default::default()
+        };
+        let xml_content = generate_xml_with_options(&entities, &inputs, None, &options).unwrap();
 
         let agents_file = temp_dir.join("AGENTS.md");
         std::fs::write(&agents_file, xml_content).unwrap();
@@ -169,7 +726,19 @@ mod tests {
             verbose: 0,
             warn_token_count: 5000,
             error_token_count: 10000,
-            command: Commands::Check { agents_file },
+            xml_escape: XmlEscape::Escape,
+            inline_max_tokens: None,
+            config: None,
+            duplicate_names: None,
+            assume: vec![],
+            command: Commands::Check {
+                agents_file,
+                template: None,
+                diff: false,
+                semantic: false,
+                report_format: None,
+                report: None,
+            },
         };
 
         assert!(handle_cli(cli).is_ok());
@@ -205,7 +774,19 @@ mod tests {
             verbose: 0,
             warn_token_count: 5000,
             error_token_count: 50, // Limit 50 tokens, content is > 200
-            command: Commands::Check { agents_file },
+            xml_escape: XmlEscape::Escape,
+            inline_max_tokens: None,
+            config: None,
+            duplicate_names: None,
+            assume: vec![],
+            command: Commands::Check {
+                agents_file,
+                template: None,
+                diff: false,
+                semantic: false,
+                report_format: None,
+                report: None,
+            },
         };
 
         assert!(handle_cli(cli).is_err());
@@ -221,7 +802,20 @@ mod tests {
             verbose: 0,
             warn_token_count: 5000,
             error_token_count: 10000,
-            command: Commands::List,
+            xml_escape: XmlEscape::Escape,
+            inline_max_tokens: None,
+            config: None,
+            duplicate_names: None,
+            assume: vec![],
+            command: Commands::List {
+                filter: None,
+                tag: vec![],
+                exclude_tag: vec![],
+                explain: false,
+                origin: false,
+                format: ListFormat::Tree,
+                long: false,
+            },
         };
         // This might print to stdout, but should return Ok
         assert!(handle_cli(cli).is_ok());
@@ -235,7 +829,20 @@ mod tests {
             verbose: 0,
             warn_token_count: 5000,
             error_token_count: 10000,
-            command: Commands::Build { output: None },
+            xml_escape: XmlEscape::Escape,
+            inline_max_tokens: None,
+            config: None,
+            duplicate_names: None,
+            assume: vec![],
+            command: Commands::Build {
+                output: None,
+                template: None,
+                filter: None,
+                tag: vec![],
+                exclude_tag: vec![],
+                persona: None,
+                agents_file: None,
+            },
         };
         assert!(handle_cli(cli).is_ok());
     }
@@ -247,8 +854,19 @@ mod tests {
             verbose: 0,
             warn_token_count: 5000,
             error_token_count: 10000,
+            xml_escape: XmlEscape::Escape,
+            inline_max_tokens: None,
+            config: None,
+            duplicate_names: None,
+            assume: vec![],
             command: Commands::Build {
                 output: Some(PathBuf::from("out")),
+                template: None,
+                filter: None,
+                tag: vec![],
+                exclude_tag: vec![],
+                persona: None,
+                agents_file: None,
             },
         };
         assert!(handle_cli(cli).is_ok());
@@ -0,0 +1,322 @@
+use persona_core::{Diagnostic, Severity, diagnostics::RULES, paths::relative_to};
+use quick_xml::Writer;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::cli::ReportFormat;
+
+/// What a `check` run found: the entity files it checked and every diagnostic raised.
+#[derive(Debug, Default)]
+pub struct CheckResults {
+    pub entities: Vec<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Renders the results of a `check` run in a machine-readable format.
+pub fn render(format: ReportFormat, results: &CheckResults) -> anyhow::Result<String> {
+    let diagnostics = &results.diagnostics;
+    let value = match format {
+        ReportFormat::Json => json_report(diagnostics),
+        ReportFormat::Sarif => sarif_report(diagnostics),
+        ReportFormat::Github => return Ok(github_report(diagnostics)),
+        ReportFormat::Junit => return junit_report(results),
+    };
+    Ok(serde_json::to_string_pretty(&value)? + "\n")
+}
+
+fn count(diagnostics: &[Diagnostic], severity: Severity) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.severity == severity)
+        .count()
+}
+
+/// Paths are reported relative to the working directory with forward slashes.
+fn uri(path: &Path) -> String {
+    relative_to(path, Path::new("."))
+}
+
+fn json_report(diagnostics: &[Diagnostic]) -> Value {
+    let entries: Vec<Value> = diagnostics
+        .iter()
+        .map(|d| {
+            json!({
+                "rule": d.rule,
+                "severity": d.severity.as_str(),
+                "message": d.message,
+                "path": d.path.as_deref().map(uri),
+                "line": d.line,
+            })
+        })
+        .collect();
+    json!({
+        "errors": count(diagnostics, Severity::Error),
+        "warnings": count(diagnostics, Severity::Warning),
+        "diagnostics": entries,
+    })
+}
+
+fn sarif_report(diagnostics: &[Diagnostic]) -> Value {
+    let rules: Vec<Value> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|d| {
+            let mut result = json!({
+                "ruleId": d.rule,
+                "level": d.severity.as_str(),
+                "message": { "text": d.message },
+            });
+            if let Some(path) = &d.path {
+                let mut location = json!({ "artifactLocation": { "uri": uri(path) } });
+                if let Some(line) = d.line {
+                    location["region"] = json!({ "startLine": line });
+                }
+                result["locations"] = json!([{ "physicalLocation": location }]);
+            }
+            result
+        })
+        .collect();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "persona",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// GitHub Actions workflow commands, which annotate the files inline on pull requests.
+fn github_report(diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for d in diagnostics {
+        let mut properties = Vec::new();
+        if let Some(path) = &d.path {
+            properties.push(format!("file={}", escape_property(&uri(path))));
+            if let Some(line) = d.line {
+                properties.push(format!("line={}", line));
+            }
+        }
+        properties.push(format!("title={}", escape_property(d.rule)));
+        let _ = writeln!(
+            out,
+            "::{} {}::{}",
+            d.severity.as_str(),
+            properties.join(","),
+            escape_data(&d.message)
+        );
+    }
+    out
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// JUnit XML with one test case per checked entity file. Diagnostics for other files
+/// (such as the agents file) get test cases of their own; errors are failures and
+/// warnings are attached as output.
+fn junit_report(results: &CheckResults) -> anyhow::Result<String> {
+    let mut cases: BTreeMap<String, Vec<&Diagnostic>> = BTreeMap::new();
+    for entity in &results.entities {
+        cases.entry(uri(entity)).or_default();
+    }
+    for d in &results.diagnostics {
+        let name = d
+            .path
+            .as_deref()
+            .map(uri)
+            .unwrap_or_else(|| "persona".to_string());
+        cases.entry(name).or_default().push(d);
+    }
+
+    let tests = cases.len().to_string();
+    let failures = cases
+        .values()
+        .filter(|ds| ds.iter().any(|d| d.severity == Severity::Error))
+        .count()
+        .to_string();
+
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer.write_event(Event::Start(BytesStart::new("testsuites").with_attributes(
+        [
+            ("name", "persona"),
+            ("tests", tests.as_str()),
+            ("failures", failures.as_str()),
+        ],
+    )))?;
+    writer.write_event(Event::Start(BytesStart::new("testsuite").with_attributes(
+        [
+            ("name", "persona check"),
+            ("tests", tests.as_str()),
+            ("failures", failures.as_str()),
+        ],
+    )))?;
+    for (name, diagnostics) in &cases {
+        let case = BytesStart::new("testcase").with_attributes([
+            ("classname", "persona"),
+            ("name", name.as_str()),
+            ("file", name.as_str()),
+        ]);
+        if diagnostics.is_empty() {
+            writer.write_event(Event::Empty(case))?;
+            continue;
+        }
+        writer.write_event(Event::Start(case))?;
+        for d in diagnostics.iter().filter(|d| d.severity == Severity::Error) {
+            writer.write_event(Event::Start(
+                BytesStart::new("failure")
+                    .with_attributes([("type", d.rule), ("message", d.message.as_str())]),
+            ))?;
+            writer.write_event(Event::Text(BytesText::new(&d.to_string())))?;
+            writer.write_event(Event::End(BytesEnd::new("failure")))?;
+        }
+        let warnings: Vec<String> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+            .map(ToString::to_string)
+            .collect();
+        if !warnings.is_empty() {
+            writer.write_event(Event::Start(BytesStart::new("system-out")))?;
+            writer.write_event(Event::Text(BytesText::new(&warnings.join("\n"))))?;
+            writer.write_event(Event::End(BytesEnd::new("system-out")))?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("testcase")))?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("testsuite")))?;
+    writer.write_event(Event::End(BytesEnd::new("testsuites")))?;
+    Ok(String::from_utf8(writer.into_inner())? + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results() -> CheckResults {
+        CheckResults {
+            entities: vec![
+                PathBuf::from("skills/rust/SKILL.md"),
+                PathBuf::from("skills/big/SKILL.md"),
+                PathBuf::from("skills/go/SKILL.md"),
+            ],
+            diagnostics: diagnostics(),
+        }
+    }
+
+    fn diagnostics() -> Vec<Diagnostic> {
+        vec![
+            Diagnostic::error("invalid-yaml", "YAML parsing error")
+                .at(Path::new("skills/rust/SKILL.md"), Some(3)),
+            Diagnostic::warning("token-warning", "Entity is large")
+                .at(Path::new("skills/big/SKILL.md"), None),
+        ]
+    }
+
+    #[test]
+    fn test_json_report() {
+        let report = render(ReportFormat::Json, &results()).unwrap();
+        let value: Value = serde_json::from_str(&report).unwrap();
+
+        assert_eq!(value["errors"], 1);
+        assert_eq!(value["warnings"], 1);
+        assert_eq!(value["diagnostics"][0]["rule"], "invalid-yaml");
+        assert_eq!(value["diagnostics"][0]["path"], "skills/rust/SKILL.md");
+        assert_eq!(value["diagnostics"][0]["line"], 3);
+        assert_eq!(value["diagnostics"][1]["severity"], "warning");
+        assert!(value["diagnostics"][1]["line"].is_null());
+    }
+
+    #[test]
+    fn test_sarif_report() {
+        let report = render(ReportFormat::Sarif, &results()).unwrap();
+        let value: Value = serde_json::from_str(&report).unwrap();
+
+        assert_eq!(value["version"], "2.1.0");
+        let run = &value["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "persona");
+        assert!(
+            run["tool"]["driver"]["rules"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|rule| rule["id"] == "invalid-yaml")
+        );
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "invalid-yaml");
+        assert_eq!(result["level"], "error");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "skills/rust/SKILL.md");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert!(run["results"][1]["locations"][0]["physicalLocation"]["region"].is_null());
+    }
+
+    #[test]
+    fn test_github_report() {
+        let mut results = results();
+        results.diagnostics.push(Diagnostic::error(
+            "agents-file-stale",
+            "AGENTS.md: 50% changed\nRun build",
+        ));
+
+        let report = render(ReportFormat::Github, &results).unwrap();
+
+        assert_eq!(
+            report,
+            "::error file=skills/rust/SKILL.md,line=3,title=invalid-yaml::YAML parsing error\n\
+             ::warning file=skills/big/SKILL.md,title=token-warning::Entity is large\n\
+             ::error title=agents-file-stale::AGENTS.md: 50%25 changed%0ARun build\n"
+        );
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let report = render(ReportFormat::Junit, &results()).unwrap();
+
+        assert!(report.contains(r#"<testsuites name="persona" tests="3" failures="1">"#));
+        assert!(report.contains(
+            r#"<testcase classname="persona" name="skills/go/SKILL.md" file="skills/go/SKILL.md"/>"#
+        ));
+        assert!(report.contains(
+            r#"<failure type="invalid-yaml" message="YAML parsing error">skills/rust/SKILL.md:3: YAML parsing error [invalid-yaml]</failure>"#
+        ));
+        assert!(report.contains(
+            "<system-out>skills/big/SKILL.md: Entity is large [token-warning]</system-out>"
+        ));
+
+        let mut results = results();
+        results.diagnostics = vec![
+            Diagnostic::error("agents-file-stale", r#"<rust> & "go" differ"#)
+                .at(Path::new("AGENTS.md"), None),
+        ];
+        let report = render(ReportFormat::Junit, &results).unwrap();
+        assert!(report.contains(
+            r#"<failure type="agents-file-stale" message="&lt;rust&gt; &amp; &quot;go&quot; differ">"#
+        ));
+    }
+}
@@ -1,3 +1,4 @@
 mod cli;
 pub use cli::Cli;
+pub use cli::error::{CliError, ExitCode};
 pub use cli::handlers::handle_cli;
@@ -1,8 +1,12 @@
-use clap::Parser;
-use persona::{Cli, handle_cli};
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use persona::{Cli, ExitCode, handle_cli};
 use tracing::Level;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> std::process::ExitCode {
+    // Run by the completion scripts with `COMPLETE` set: prints candidates and exits.
+    CompleteEnv::with_factory(Cli::command).complete();
+
     let cli = Cli::parse();
 
     // Initialize tracing based on verbosity
@@ -18,6 +22,11 @@ fn main() -> anyhow::Result<()> {
         .with_writer(std::io::stderr)
         .init();
 
-    handle_cli(cli)?;
-    Ok(())
+    match handle_cli(cli) {
+        Ok(()) => ExitCode::Success.into(),
+        Err(e) => {
+            eprintln!("Error: {:?}", e.error);
+            e.code.into()
+        }
+    }
 }
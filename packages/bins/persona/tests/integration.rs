@@ -31,24 +31,18 @@ Use rust for systems programming.
 "#;
     fs::write(&skill_file, content).unwrap();
 
-    // Generate expected AGENTS.md content
+    // Generate expected AGENTS.md content. Paths are relative to the AGENTS.md directory.
     let agents_file = temp.join("AGENTS.md");
-    // Note: generate_xml uses entity.path for the path attribute.
-    // Since we pass absolute path as input, entity.path is absolute.
-    // Ideally we should use relative paths, but for this test we match what generate_xml produces.
-    let expected_xml = format!(
-        r#"<persona-context>
+    let expected_xml = r#"<persona-context>
   <skills>
     <coding>
-      <rust path="{}">
+      <rust path="inputs/skills/coding/rust/SKILL.md">
         <description>Rust programming skill</description>
       </rust>
     </coding>
   </skills>
-</persona-context>"#,
-        skill_file.to_string_lossy()
-    );
-    fs::write(&agents_file, &expected_xml).unwrap();
+</persona-context>"#;
+    fs::write(&agents_file, expected_xml).unwrap();
 
     // Mock CLI arguments
     let cli = Cli::parse_from([
@@ -228,3 +222,1187 @@ Use rust.
 
     fs::remove_dir_all(root).unwrap();
 }
+
+#[test]
+fn test_check_with_template_from_input_tree() {
+    let temp = setup_temp_dir("template_check");
+    let root = temp.join("inputs");
+    let skill_dir = root.join("skills/coding/rust");
+    fs::create_dir_all(&skill_dir).unwrap();
+
+    let content = r#"---
+name: rust
+description: Rust programming skill
+---
+Use rust for systems programming.
+"#;
+    fs::write(skill_dir.join("SKILL.md"), content).unwrap();
+    fs::write(
+        root.join("AGENTS.md.j2"),
+        "# Agents\n{% for e in entities %}\n- {{ e.name }}: {{ e.description }}\n{% endfor %}",
+    )
+    .unwrap();
+
+    let agents_file = temp.join("AGENTS.md");
+    fs::write(&agents_file, "# Agents\n- rust: Rust programming skill\n").unwrap();
+
+    let cli = Cli::parse_from([
+        "persona",
+        "-i",
+        root.to_str().unwrap(),
+        "check",
+        "--agents-file",
+        agents_file.to_str().unwrap(),
+        "--template",
+        "AGENTS.md.j2",
+    ]);
+
+    handle_cli(cli).unwrap();
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_check_semantic_ignores_formatting() {
+    let temp = setup_temp_dir("semantic_check");
+    let root = temp.join("inputs");
+    let skill_dir = root.join("skills/coding/rust");
+    fs::create_dir_all(&skill_dir).unwrap();
+
+    let content = r#"---
+name: rust
+description: Rust programming skill
+---
+Use rust for systems programming.
+"#;
+    fs::write(skill_dir.join("SKILL.md"), content).unwrap();
+
+    // Reformatted by an editor: different indentation and a trailing newline.
+    let agents_file = temp.join("AGENTS.md");
+    fs::write(
+        &agents_file,
+        "<persona-context>\n<skills><coding>\n<rust path=\"inputs/skills/coding/rust/SKILL.md\">\n<description>\n  Rust programming skill\n</description>\n</rust>\n</coding></skills>\n</persona-context>\n",
+    )
+    .unwrap();
+
+    let args = |semantic: bool| {
+        let mut args = vec![
+            "persona",
+            "-i",
+            root.to_str().unwrap(),
+            "check",
+            "--agents-file",
+            agents_file.to_str().unwrap(),
+        ];
+        if semantic {
+            args.push("--semantic");
+        }
+        Cli::parse_from(args)
+    };
+
+    assert!(handle_cli(args(false)).is_err());
+    handle_cli(args(true)).unwrap();
+
+    // A real change is still reported.
+    let edited = fs::read_to_string(&agents_file)
+        .unwrap()
+        .replace("Rust programming skill", "Rust skill");
+    fs::write(&agents_file, edited).unwrap();
+    assert!(handle_cli(args(true)).is_err());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_check_sarif_report() {
+    let temp = setup_temp_dir("sarif_report");
+    let root = temp.join("inputs");
+    let valid_dir = root.join("skills/rust");
+    let invalid_dir = root.join("skills/python");
+    fs::create_dir_all(&valid_dir).unwrap();
+    fs::create_dir_all(&invalid_dir).unwrap();
+    fs::write(
+        valid_dir.join("SKILL.md"),
+        "---\nname: rust\ndescription: Rust skill\n---\nBody",
+    )
+    .unwrap();
+    fs::write(
+        invalid_dir.join("SKILL.md"),
+        "---\nname: python\ndescription: [unclosed\n---\nBody",
+    )
+    .unwrap();
+
+    let report = temp.join("persona.sarif");
+    let cli = Cli::parse_from([
+        "persona",
+        "-i",
+        root.to_str().unwrap(),
+        "check",
+        "--agents-file",
+        temp.join("AGENTS.md").to_str().unwrap(),
+        "--report-format",
+        "sarif",
+        "--report",
+        report.to_str().unwrap(),
+    ]);
+
+    // The report does not change the outcome of the check.
+    assert!(handle_cli(cli).is_err());
+
+    let sarif: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let results = sarif["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], "invalid-yaml");
+    let location = &results[0]["locations"][0]["physicalLocation"];
+    assert!(
+        location["artifactLocation"]["uri"]
+            .as_str()
+            .unwrap()
+            .ends_with("inputs/skills/python/SKILL.md")
+    );
+    assert_eq!(location["region"]["startLine"], 3);
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_exit_codes() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("exit_codes");
+    let root = temp.join("inputs");
+    let skill_dir = root.join("skills/rust");
+    fs::create_dir_all(&skill_dir).unwrap();
+    fs::write(
+        skill_dir.join("SKILL.md"),
+        "---\nname: rust\ndescription: Rust skill\n---\nUse rust for systems programming.",
+    )
+    .unwrap();
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    // Missing AGENTS.md is stale; building fixes it.
+    assert_eq!(persona(&["check"]), Some(5));
+    assert_eq!(persona(&["build"]), Some(0));
+    assert_eq!(persona(&["check"]), Some(0));
+
+    fs::write(temp.join("AGENTS.md"), "<persona-context/>\n").unwrap();
+    assert_eq!(persona(&["check"]), Some(5));
+
+    assert_eq!(persona(&["--error-token-count", "1", "check"]), Some(4));
+    assert_eq!(persona(&["check", "--agents-file", "inputs"]), Some(6));
+    assert_eq!(persona(&["check", "--bogus"]), Some(2));
+
+    fs::write(
+        skill_dir.join("SKILL.md"),
+        "---\nname: python\ndescription: Rust skill\n---\nBody",
+    )
+    .unwrap();
+    assert_eq!(persona(&["check"]), Some(3));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_new_entity_passes_check() {
+    let temp = setup_temp_dir("new_entity");
+    let root = temp.join("inputs");
+    fs::create_dir_all(&root).unwrap();
+    let input = root.to_str().unwrap();
+
+    handle_cli(Cli::parse_from([
+        "persona",
+        "-i",
+        input,
+        "new",
+        "skill",
+        "coding/rust-review",
+    ]))
+    .unwrap();
+    assert!(root.join("skills/coding/rust-review/SKILL.md").exists());
+
+    // The scaffolded entity is valid, and a second run refuses to overwrite it.
+    persona_core::collect_entities(std::slice::from_ref(&root), 5000, 10000).unwrap();
+    assert!(
+        handle_cli(Cli::parse_from([
+            "persona",
+            "-i",
+            input,
+            "new",
+            "skill",
+            "coding/rust-review",
+        ]))
+        .is_err()
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_mv_dry_run_and_apply() {
+    let temp = setup_temp_dir("mv_entity");
+    let root = temp.join("inputs");
+    let skill_dir = root.join("skills/coding/rust");
+    fs::create_dir_all(&skill_dir).unwrap();
+    fs::write(
+        skill_dir.join("SKILL.md"),
+        "---\nname: rust\ndescription: Rust skill\n---\nUse rust.",
+    )
+    .unwrap();
+    let input = root.to_str().unwrap();
+
+    handle_cli(Cli::parse_from([
+        "persona",
+        "-i",
+        input,
+        "mv",
+        "rust",
+        "lang/rust-lang",
+        "--dry-run",
+    ]))
+    .unwrap();
+    assert!(skill_dir.exists());
+
+    handle_cli(Cli::parse_from([
+        "persona",
+        "-i",
+        input,
+        "mv",
+        "coding/rust",
+        "lang/rust-lang",
+    ]))
+    .unwrap();
+    assert!(!skill_dir.exists());
+    let moved = fs::read_to_string(root.join("skills/lang/rust-lang/SKILL.md")).unwrap();
+    // Prose mentioning the old name is left untouched.
+    assert_eq!(
+        moved,
+        "---\nname: rust-lang\ndescription: Rust skill\n---\nUse rust."
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_mv_rebuilds_every_agents_file() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("mv_rebuild");
+    let dir = temp.join("inputs/skills/coding/rust");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("SKILL.md"),
+        "---\nname: rust\ndescription: Rust skill\ntags: [backend]\n---\nBody",
+    )
+    .unwrap();
+    fs::write(
+        temp.join("persona.yaml"),
+        "profiles:\n  backend:\n    tags: [backend]\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.join("list.j2"),
+        "{% for e in entities %}{{ e.name }}\n{% endfor %}",
+    )
+    .unwrap();
+    fs::write(temp.join("AGENTS.md"), "rust\n").unwrap();
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let dry_run = persona(&["mv", "rust", "lang/rust-lang", "--dry-run"]);
+    assert!(dry_run.status.success());
+    let stdout = String::from_utf8(dry_run.stdout).unwrap();
+    assert!(stdout.contains("Would rebuild AGENTS.md"), "{stdout}");
+    assert!(
+        stdout.contains("Would rebuild AGENTS.backend.md"),
+        "{stdout}"
+    );
+    assert!(!temp.join("AGENTS.backend.md").exists());
+
+    let moved = persona(&["mv", "rust", "lang/rust-lang", "--template", "list.j2"]);
+    assert!(moved.status.success());
+    assert_eq!(
+        fs::read_to_string(temp.join("AGENTS.md")).unwrap(),
+        "rust-lang\n"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.join("AGENTS.backend.md")).unwrap(),
+        "rust-lang\n"
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_show_json() {
+    let temp = setup_temp_dir("show_entity");
+    let root = temp.join("inputs");
+    let skill_dir = root.join("skills/coding/rust");
+    fs::create_dir_all(&skill_dir).unwrap();
+    fs::write(
+        skill_dir.join("SKILL.md"),
+        "---\nname: rust\ndescription: Rust skill\n---\nUse rust.",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_persona"))
+        .args([
+            "-i",
+            root.to_str().unwrap(),
+            "show",
+            "coding/rust",
+            "--json",
+        ])
+        .args(["--render"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let details: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(details["name"], "rust");
+    assert_eq!(details["kind"], "skill");
+    assert_eq!(details["frontmatter"]["description"], "Rust skill");
+    assert_eq!(details["body"], "Use rust.");
+    assert!(
+        details["rendered"]
+            .as_str()
+            .unwrap()
+            .contains("<content>Use rust.</content>")
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_search_with_cache() {
+    let temp = setup_temp_dir("search");
+    let root = temp.join("inputs");
+    for (name, description, body) in [
+        (
+            "migrations",
+            "Database migrations",
+            "Keep database migrations reversible.",
+        ),
+        ("rust", "Rust skill", "Use rust for systems programming."),
+    ] {
+        let dir = root.join("skills").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\nname: {name}\ndescription: {description}\n---\n{body}"),
+        )
+        .unwrap();
+    }
+    let cache = temp.join("cache/index.json");
+
+    let search = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_persona"))
+            .args(["-i", root.to_str().unwrap(), "search", "kind:skill"])
+            .args(["database", "--cache", cache.to_str().unwrap()])
+            .output()
+            .unwrap()
+    };
+
+    for _ in 0..2 {
+        let output = search();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with("1. migrations (skill) "));
+        assert!(stdout.contains("   Keep database migrations reversible.\n"));
+        assert!(!stdout.contains("rust"));
+        assert!(cache.exists());
+    }
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_query_and_list_filter() {
+    let temp = setup_temp_dir("query");
+    let root = temp.join("inputs");
+    for (path, frontmatter) in [
+        (
+            "skills/vcs/jj",
+            "name: jj\ndescription: Jujutsu\ncompatibility: Requires jj",
+        ),
+        (
+            "skills/vcs/git",
+            "name: git\ndescription: Git\nlicense: MIT",
+        ),
+        ("skills/lang/rust", "name: rust\ndescription: Rust"),
+    ] {
+        let dir = root.join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\n{frontmatter}\n---\nBody"),
+        )
+        .unwrap();
+    }
+    let persona = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_persona"))
+            .args(["-i", root.to_str().unwrap()])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = persona(&["query", "compatibility contains JJ or exists(license)"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let names: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(names, vec!["git", "jj"]);
+
+    let output = persona(&["list", "--filter", "path matches 'skills/lang/**'"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("rust"));
+    assert!(!stdout.contains("git"));
+    assert!(!stdout.contains("jj"));
+
+    let output = persona(&["query", "name ="]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Invalid query")
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_requires_validation_and_rendering() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("requires");
+    let write = |path: &str, frontmatter: &str| {
+        let dir = temp.join("inputs").join(path);
+        fs::create_dir_all(&dir).unwrap();
+        let file = if path.starts_with("personas") {
+            "PERSONA.md"
+        } else {
+            "SKILL.md"
+        };
+        fs::write(dir.join(file), format!("---\n{frontmatter}\n---\nBody")).unwrap();
+    };
+    write("skills/vcs/git", "name: git\ndescription: Git");
+    write(
+        "personas/dev",
+        "name: dev\ndescription: Developer\nrequires: [vcs/git]",
+    );
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(persona(&["build"]).status.success());
+    let agents = fs::read_to_string(temp.join("AGENTS.md")).unwrap();
+    assert!(agents.contains(
+        "<requires>\n        <item path=\"inputs/skills/vcs/git/SKILL.md\">git</item>\n      </requires>"
+    ));
+
+    write(
+        "skills/vcs/git",
+        "name: git\ndescription: Git\nrequires: [dev]\ndeprecated: true",
+    );
+    let output = persona(&["check", "--report-format", "json"]);
+    assert_eq!(output.status.code(), Some(3));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut rules: Vec<&str> = report["diagnostics"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["rule"].as_str().unwrap())
+        .collect();
+    rules.sort();
+    assert_eq!(rules, vec!["deprecated-requirement", "requirement-cycle"]);
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_graph_formats() {
+    let temp = setup_temp_dir("graph");
+    let root = temp.join("inputs");
+    for (path, file, frontmatter) in [
+        ("skills/vcs/git", "SKILL.md", "name: git\ndescription: Git"),
+        ("skills/vcs/svn", "SKILL.md", "name: svn\ndescription: Svn"),
+        (
+            "personas/dev",
+            "PERSONA.md",
+            "name: dev\ndescription: Dev\nrequires: [git]",
+        ),
+    ] {
+        let dir = root.join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), format!("---\n{frontmatter}\n---\nBody")).unwrap();
+    }
+    let graph = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_persona"))
+            .args(["-i", root.to_str().unwrap(), "graph"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let dot = graph(&["--orphans"]);
+    assert!(dot.starts_with("digraph persona {"));
+    assert!(dot.contains("\"personas/dev\" -> \"skills/vcs/git\";"));
+    assert!(dot.contains("\"skills/vcs/svn\" [label=\"svn\", style=filled"));
+    assert!(dot.contains("\"skills/vcs/git\" [label=\"git\"];"));
+
+    let mermaid = graph(&["--format", "mermaid", "--focus", "dev"]);
+    assert!(mermaid.starts_with("flowchart LR\n"));
+    assert!(mermaid.contains("[\"git\"]"));
+    assert!(!mermaid.contains("svn"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_build_persona_bundle() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("persona_bundle");
+    let write = |path: &str, file: &str, frontmatter: &str, body: &str| {
+        let dir = temp.join("inputs").join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), format!("---\n{frontmatter}\n---\n{body}")).unwrap();
+    };
+    write(
+        "personas/qa",
+        "PERSONA.md",
+        "name: qa\ndescription: QA engineer\nskills: [testing]",
+        "Break things carefully.",
+    );
+    write(
+        "skills/testing",
+        "SKILL.md",
+        "name: testing\ndescription: Testing\nrequires: [git]",
+        "Body",
+    );
+    write(
+        "skills/git",
+        "SKILL.md",
+        "name: git\ndescription: Git",
+        "Body",
+    );
+    write(
+        "skills/docker",
+        "SKILL.md",
+        "name: docker\ndescription: Docker",
+        "Body",
+    );
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(persona(&["build", "--persona", "qa"]).status.success());
+    let agents = fs::read_to_string(temp.join("AGENTS.qa.md")).unwrap();
+    assert!(agents.contains("<content>Break things carefully.</content>"));
+    assert!(agents.contains("<testing path=\"inputs/skills/testing/SKILL.md\">"));
+    assert!(agents.contains("<git path="));
+    assert!(!agents.contains("docker"));
+    assert!(!temp.join("AGENTS.md").exists());
+
+    write(
+        "personas/qa",
+        "PERSONA.md",
+        "name: qa\ndescription: QA engineer\nskills: [testing, fuzzing]",
+        "Body",
+    );
+    let output = persona(&["build", "--persona", "qa"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("unknown-skill")
+    );
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_tag_profiles() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("tag_profiles");
+    let write = |name: &str, tags: &str| {
+        let dir = temp.join("inputs/skills").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\nname: {name}\ndescription: {name} skill\ntags: {tags}\n---\nBody"),
+        )
+        .unwrap();
+    };
+    write("api", "[backend]");
+    write("css", "[frontend]");
+    write("git", "[backend, frontend]");
+    fs::write(
+        temp.join("persona.yaml"),
+        "profiles:\n  backend:\n    tags: [backend]\n  web:\n    tags: [frontend]\n    output: web/AGENTS.md\n",
+    )
+    .unwrap();
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let list = persona(&["list", "--tag", "backend", "--exclude-tag", "frontend"]);
+    let stdout = String::from_utf8(list.stdout).unwrap();
+    assert!(stdout.contains("api"));
+    assert!(!stdout.contains("git"));
+    assert!(!stdout.contains("css"));
+
+    assert!(persona(&["build"]).status.success());
+    let backend = fs::read_to_string(temp.join("AGENTS.backend.md")).unwrap();
+    assert!(backend.contains("<api ") && backend.contains("<git "));
+    assert!(!backend.contains("<css "));
+    let web = fs::read_to_string(temp.join("web/AGENTS.md")).unwrap();
+    assert!(web.contains("path=\"../inputs/skills/css/SKILL.md\""));
+    assert!(!web.contains("<api "));
+    assert!(persona(&["check"]).status.success());
+
+    // Ad-hoc selections go to a file of their own, so check stays green.
+    assert!(!persona(&["build", "--tag", "backend"]).status.success());
+    let into_checked = persona(&[
+        "build",
+        "--filter",
+        "name = api",
+        "--agents-file",
+        "./AGENTS.md",
+    ]);
+    assert!(!into_checked.status.success());
+    assert!(
+        !persona(&[
+            "build",
+            "--tag",
+            "backend",
+            "--agents-file",
+            "AGENTS.backend.md"
+        ])
+        .status
+        .success()
+    );
+    let subset = persona(&[
+        "build",
+        "--exclude-tag",
+        "backend",
+        "--agents-file",
+        "css.md",
+    ]);
+    assert!(subset.status.success());
+    let css = fs::read_to_string(temp.join("css.md")).unwrap();
+    assert!(css.contains("<css ") && !css.contains("<api "));
+    assert!(persona(&["check"]).status.success());
+
+    // Every stale profile is reported, not just the first.
+    write("css", "[frontend, backend]");
+    let check = persona(&["check"]);
+    assert_eq!(check.status.code(), Some(5));
+    let stderr = String::from_utf8(check.stderr).unwrap();
+    assert!(stderr.contains("+++ AGENTS.backend.md"));
+    assert!(stderr.contains("+++ web/AGENTS.md"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_build_fails_when_a_requirement_is_left_out() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("requirement_left_out");
+    let write = |name: &str, extra: &str| {
+        let dir = temp.join("inputs/skills").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\nname: {name}\ndescription: {name} skill\n{extra}\n---\nBody"),
+        )
+        .unwrap();
+    };
+    write("a", "tags: [frontend]\nrequires: [b]");
+    write("b", "tags: [backend]");
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(persona(&["build"]).status.success());
+    assert!(persona(&["check"]).status.success());
+
+    // Left out by a tag
+    let build = persona(&["build", "--tag", "frontend", "--agents-file", "out.md"]);
+    assert_eq!(build.status.code(), Some(3));
+    let stderr = String::from_utf8(build.stderr).unwrap();
+    assert!(stderr.contains("'a' requires 'b', which this build leaves out"));
+    assert!(!temp.join("out.md").exists());
+
+    // Left out by its `when` condition
+    write("b", "when:\n  binary: persona-test-missing-binary");
+    assert_eq!(persona(&["build"]).status.code(), Some(3));
+    assert_eq!(persona(&["check"]).status.code(), Some(3));
+    let assume = ["--assume", "binary:persona-test-missing-binary", "build"];
+    assert!(persona(&assume).status.success());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_when_conditions() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("when_conditions");
+    for (name, when) in [
+        ("jj", "binary: persona-test-missing-binary"),
+        ("cargo", "file: Cargo.toml"),
+        ("git", "os: [linux, macos, windows]"),
+    ] {
+        let dir = temp.join("inputs/skills").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\nname: {name}\ndescription: {name} skill\nwhen:\n  {when}\n---\nBody"),
+        )
+        .unwrap();
+    }
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs", "--assume", "os:linux"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let list = persona(&["list", "--explain"]);
+    let stdout = String::from_utf8(list.stdout).unwrap();
+    let (listed, excluded) = stdout.split_once("Excluded:").unwrap();
+    assert!(listed.contains("git"));
+    assert!(!listed.contains("jj") && !listed.contains("cargo"));
+    assert!(excluded.contains("'persona-test-missing-binary' is not on PATH"));
+    assert!(excluded.contains("'Cargo.toml' does not exist"));
+
+    assert!(persona(&["build"]).status.success());
+    let agents = fs::read_to_string(temp.join("AGENTS.md")).unwrap();
+    assert!(agents.contains("<git ") && !agents.contains("<jj "));
+    assert!(persona(&["check"]).status.success());
+
+    // Assumptions make the build reproducible regardless of the machine.
+    let assume = ["--assume", "binary:persona-test-missing-binary"];
+    assert_eq!(
+        persona(&[&assume[..], &["check"]].concat()).status.code(),
+        Some(5)
+    );
+    assert!(
+        persona(&[&assume[..], &["build"]].concat())
+            .status
+            .success()
+    );
+    let agents = fs::read_to_string(temp.join("AGENTS.md")).unwrap();
+    assert!(agents.contains("<jj "));
+
+    let invalid = persona(&["--assume", "shell:zsh", "list"]);
+    assert_eq!(invalid.status.code(), Some(2));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_build_output_leaves_out_excluded_entities() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("build_output_subset");
+    let skills = temp.join("inputs/skills");
+    fs::create_dir_all(&skills).unwrap();
+    fs::write(skills.join("HEADER.md"), "Skills header").unwrap();
+    for (name, extra) in [
+        ("a", "tags: [frontend]"),
+        ("b", "tags: [backend]"),
+        (
+            "c",
+            "tags: [frontend]\nwhen:\n  binary: persona-test-missing-binary",
+        ),
+    ] {
+        let dir = skills.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\nname: {name}\ndescription: {name} skill\n{extra}\n---\nBody"),
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "notes").unwrap();
+    }
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let build = persona(&[
+        "build",
+        "--tag",
+        "frontend",
+        "--agents-file",
+        "out.md",
+        "--output",
+        "outdir",
+    ]);
+    assert!(build.status.success());
+    let out = temp.join("outdir/skills");
+    assert!(out.join("HEADER.md").exists());
+    assert!(out.join("a/SKILL.md").exists());
+    assert!(out.join("a/notes.txt").exists());
+    // Excluded by the tag, and by its `when` condition
+    assert!(!out.join("b").exists());
+    assert!(!out.join("c").exists());
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_layered_inputs() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("layered_inputs");
+    let write = |input: &str, name: &str, content: &str| {
+        let dir = temp.join(input).join("skills").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("SKILL.md"), content).unwrap();
+    };
+    write(
+        "shared",
+        "git",
+        "---\nname: git\ndescription: Shared git\n---\nShared",
+    );
+    write(
+        "shared",
+        "rust",
+        "---\nname: rust\ndescription: Shared rust\n---\nRust",
+    );
+    write(
+        "shared",
+        "svn",
+        "---\nname: svn\ndescription: Shared svn\n---\nSvn",
+    );
+    write(
+        "project",
+        "git",
+        "---\nname: git\ndescription: Project git\nlayer: override\n---\nProject",
+    );
+    write(
+        "project",
+        "rust",
+        "---\nname: rust\nlayer: extend\ninline: true\n---\n",
+    );
+    write("project", "svn", "---\nname: svn\nlayer: remove\n---\n");
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "shared", "-i", "project"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let list = persona(&["list", "--origin"]);
+    assert!(list.status.success());
+    let stdout = String::from_utf8(list.stdout).unwrap();
+    assert!(stdout.contains("git (from shared, overridden by project)"));
+    assert!(stdout.contains("rust (from shared, extended by project)"));
+    assert!(!stdout.contains("svn"));
+
+    assert!(persona(&["build"]).status.success());
+    let agents = fs::read_to_string(temp.join("AGENTS.md")).unwrap();
+    assert!(agents.contains("Project git"));
+    assert!(agents.contains("path=\"project/skills/git/SKILL.md\""));
+    assert!(agents.contains("Shared rust") && agents.contains("Rust"));
+    assert!(!agents.contains("svn"));
+    assert!(persona(&["check"]).status.success());
+
+    // Redefining an entity without a directive is an error.
+    write(
+        "project",
+        "rust",
+        "---\nname: rust\ndescription: Mine\n---\nMine",
+    );
+    let list = persona(&["list"]);
+    assert_eq!(list.status.code(), Some(3));
+    let stderr = String::from_utf8(list.stderr).unwrap();
+    assert!(stderr.contains("set `layer: override`, `extend` or `remove`"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_duplicate_name_policy() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("duplicate_names");
+    for (path, file) in [
+        ("skills/lang/rust", "SKILL.md"),
+        ("personas/rust", "PERSONA.md"),
+    ] {
+        let dir = temp.join("inputs").join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(file),
+            "---\nname: rust\ndescription: Rust\n---\nBody",
+        )
+        .unwrap();
+    }
+
+    let persona = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let rules = |args: &[&str]| {
+        let output = persona(&[args, &["check", "--report-format", "json"]].concat());
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        report["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|d| d["rule"] == "duplicate-name")
+            .map(|d| {
+                (
+                    d["severity"].as_str().unwrap().to_string(),
+                    d["message"].clone(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Warnings by default, so existing inputs keep building.
+    let warnings = rules(&[]);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].0, "warning");
+    let message = warnings[0].1.as_str().unwrap();
+    assert!(message.contains("personas/rust/PERSONA.md"));
+    assert!(message.contains("skills/lang/rust/SKILL.md"));
+    assert!(persona(&["build"]).status.success());
+
+    assert!(rules(&["--duplicate-names", "allow-namespaced"]).is_empty());
+
+    fs::write(temp.join("persona.yaml"), "duplicate_names: error\n").unwrap();
+    assert_eq!(rules(&[])[0].0, "error");
+    assert_eq!(persona(&["list"]).status.code(), Some(3));
+    assert!(
+        persona(&["--duplicate-names", "warn", "list"])
+            .status
+            .success()
+    );
+
+    // Commands that do not use the configuration work without a valid one.
+    fs::write(temp.join("persona.yaml"), "duplicate_names: [").unwrap();
+    assert_eq!(persona(&["list"]).status.code(), Some(1));
+    for args in [
+        &["show", "skills/lang/rust"][..],
+        &["search", "rust"],
+        &["--config", "missing.yaml", "show", "personas/rust"],
+        &["completions", "bash"],
+        &["new", "skill", "lang/go"],
+    ] {
+        assert!(persona(args).status.success(), "{args:?}");
+    }
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_list_formats() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("list_formats");
+    for (path, frontmatter) in [
+        ("skills/vcs/git", "name: git\ndescription: Version control"),
+        ("skills/rust", "name: rust\ndescription: Rust conventions"),
+    ] {
+        let dir = temp.join("inputs").join(path);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            format!("---\n{frontmatter}\n---\n{}", "x".repeat(100)),
+        )
+        .unwrap();
+    }
+    let persona = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(["-i", "inputs"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(persona(&["list"]), "skills/\n  rust\n  vcs/\n    git\n");
+    assert_eq!(
+        persona(&["list", "--format", "flat"]),
+        "skills/rust\nskills/vcs/git\n"
+    );
+    let long = persona(&["list", "--long"]);
+    let git = long.lines().find(|line| line.contains("git")).unwrap();
+    assert!(git.contains("skill"));
+    assert!(git.contains(" 20 "));
+    assert!(git.contains("inputs/skills/vcs/git/SKILL.md"));
+    assert!(git.ends_with("Version control"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&persona(&["list", "--format", "json", "--explain"])).unwrap();
+    let entities = json["entities"].as_array().unwrap();
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[1]["id"], "skills/vcs/git");
+    assert_eq!(entities[1]["kind"], "skill");
+    assert_eq!(entities[1]["tokens"], 20);
+    assert_eq!(entities[1]["description"], "Version control");
+    assert_eq!(json["excluded"], serde_json::json!([]));
+
+    fs::remove_dir_all(temp).unwrap();
+}
+
+#[test]
+fn test_completions() {
+    use std::process::Command;
+
+    let temp = setup_temp_dir("completions");
+    for path in ["skills/vcs/git", "skills/vcs/jj", "personas/eng/qa"] {
+        let dir = temp.join("inputs").join(path);
+        fs::create_dir_all(&dir).unwrap();
+        let kind = if path.starts_with("skills") {
+            "SKILL.md"
+        } else {
+            "PERSONA.md"
+        };
+        let name = path.rsplit('/').next().unwrap();
+        fs::write(
+            dir.join(kind),
+            format!("---\nname: {name}\ndescription: {name}\n---\nBody"),
+        )
+        .unwrap();
+    }
+    fs::write(
+        temp.join("inputs/skills/vcs/jj/SKILL.md"),
+        "---\nname: jj\ndescription: jj\ntags: [vcs, jj]\n---\nBody",
+    )
+    .unwrap();
+    let persona = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    // The values offered for the last word, as the completion scripts ask for them.
+    let complete = |words: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_persona"))
+            .current_dir(&temp)
+            .env("COMPLETE", "fish")
+            .args(["--", "persona"])
+            .args(words)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').next().unwrap().to_string())
+            .filter(|value| !value.starts_with('-'))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(complete(&["-i", "inputs", "show", ""]), ["git", "jj", "qa"]);
+    assert_eq!(complete(&["graph", "-i", "inputs", "--focus", "j"]), ["jj"]);
+    assert_eq!(
+        complete(&["-i", "inputs", "build", "--persona", ""]),
+        ["qa"]
+    );
+    // Categories are those of the kind being created or moved.
+    assert_eq!(complete(&["-i", "inputs", "new", "skill", ""]), ["vcs/"]);
+    assert_eq!(complete(&["-i", "inputs", "new", "persona", ""]), ["eng/"]);
+    assert_eq!(complete(&["-i", "inputs", "mv", "qa", ""]), ["eng/"]);
+    assert_eq!(
+        complete(&["-i", "inputs", "list", "--tag", ""]),
+        ["jj", "vcs"]
+    );
+    assert_eq!(
+        complete(&["-i", "inputs", "build", "--exclude-tag", "v"]),
+        ["vcs"]
+    );
+    assert_eq!(
+        complete(&["-i", "inputs", "list", "--filter", "kind"]),
+        ["kind = persona", "kind = skill"]
+    );
+    // Completion never fails while typing, even without inputs.
+    assert!(complete(&["-i", "missing", "show", ""]).is_empty());
+
+    for shell in ["bash", "zsh", "fish"] {
+        let script = persona(&["completions", shell]);
+        // Values are completed by running the binary.
+        assert!(script.contains("COMPLETE"), "{shell}");
+        assert!(script.contains("persona"), "{shell}");
+    }
+    let nushell = persona(&["completions", "nushell"]);
+    assert!(nushell.contains("export extern \"persona mv\""));
+    let help = persona(&["--help"]);
+    assert!(help.contains("completions"));
+    assert!(!help.contains("__complete"));
+
+    fs::remove_dir_all(temp).unwrap();
+}
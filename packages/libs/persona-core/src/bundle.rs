@@ -0,0 +1,166 @@
+//! Persona bundles: a persona together with the skills it lists under `skills` and
+//! everything those entities require, for persona-specific agents files.
+use crate::requires::resolve_requirements;
+use crate::resolve::find_entity_of_kind;
+use crate::{EntityOrHeader, PersonaError, retain_entities};
+use persona_parser::ParsedEntity;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Kind of entities that may list skills.
+pub const PERSONA_KIND: &str = "persona";
+/// Kind of entities listed under `skills`.
+pub const SKILL_KIND: &str = "skill";
+
+/// Resolves each skill a persona lists among the skill entities.
+pub fn resolve_skills<'a>(
+    entity: &'a ParsedEntity,
+    items: &'a [EntityOrHeader],
+    inputs: &[PathBuf],
+) -> Vec<Result<&'a ParsedEntity, PersonaError>> {
+    entity
+        .frontmatter
+        .skills
+        .iter()
+        .map(|skill| find_entity_of_kind(items, inputs, skill, Some(SKILL_KIND)))
+        .collect()
+}
+
+/// Keeps the persona `query` refers to, the skills it lists, everything they require
+/// (transitively, including skills of required personas), and the headers of categories
+/// holding one of them. The persona's body is always inlined.
+pub fn bundle(
+    items: Vec<EntityOrHeader>,
+    inputs: &[PathBuf],
+    query: &str,
+) -> Result<Vec<EntityOrHeader>, PersonaError> {
+    let persona = find_entity_of_kind(&items, inputs, query, Some(PERSONA_KIND))?;
+    let persona_path = persona.path.clone();
+
+    let mut kept: HashSet<PathBuf> = HashSet::from([persona_path.clone()]);
+    let mut queue = vec![persona];
+    while let Some(entity) = queue.pop() {
+        let mut next: Vec<&ParsedEntity> = resolve_requirements(entity, &items, inputs)
+            .into_iter()
+            .map(|(_, required)| required)
+            .collect::<Result<_, _>>()?;
+        if entity.kind() == PERSONA_KIND {
+            next.extend(
+                resolve_skills(entity, &items, inputs)
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+        }
+        for entity in next {
+            if kept.insert(entity.path.clone()) {
+                queue.push(entity);
+            }
+        }
+    }
+
+    let mut items = retain_entities(items, |e| kept.contains(&e.path));
+    for item in &mut items {
+        if let EntityOrHeader::Entity(e) = item
+            && e.path == persona_path
+        {
+            e.frontmatter.inline = Some(true);
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(path: &str, name: &str, requires: &[&str], skills: &[&str]) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                requires: requires.iter().map(|r| r.to_string()).collect(),
+                skills: skills.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity(
+                "/in/personas/qa/PERSONA.md",
+                "qa",
+                &[],
+                &["testing", "lang/rust"],
+            ),
+            entity("/in/personas/rust/PERSONA.md", "rust", &[], &[]),
+            entity("/in/skills/testing/SKILL.md", "testing", &["git"], &[]),
+            entity("/in/skills/lang/rust/SKILL.md", "rust", &[], &[]),
+            entity("/in/skills/git/SKILL.md", "git", &[], &[]),
+            entity("/in/skills/docker/SKILL.md", "docker", &[], &[]),
+        ]
+    }
+
+    #[test]
+    fn test_bundle_expands_skills_transitively() {
+        let inputs = vec![PathBuf::from("/in")];
+        let bundle = bundle(items(), &inputs, "qa").unwrap();
+        let mut names: Vec<(String, Option<bool>)> = bundle
+            .iter()
+            .filter_map(|item| match item {
+                EntityOrHeader::Entity(e) => {
+                    Some((e.path.display().to_string(), e.frontmatter.inline))
+                }
+                EntityOrHeader::Header(_) => None,
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                ("/in/personas/qa/PERSONA.md".to_string(), Some(true)),
+                ("/in/skills/git/SKILL.md".to_string(), None),
+                ("/in/skills/lang/rust/SKILL.md".to_string(), None),
+                ("/in/skills/testing/SKILL.md".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bundle_errors() {
+        let inputs = vec![PathBuf::from("/in")];
+        // Only personas can be bundled.
+        assert!(matches!(
+            bundle(items(), &inputs, "testing"),
+            Err(PersonaError::EntityNotFound(_))
+        ));
+
+        let mut items = items();
+        items.push(entity(
+            "/in/personas/dev/PERSONA.md",
+            "dev",
+            &[],
+            &["python"],
+        ));
+        items.push(entity("/in/personas/ops/PERSONA.md", "ops", &[], &["git"]));
+        items.push(entity("/in/skills/extra/git/SKILL.md", "git", &[], &[]));
+
+        let (dev, ops) = match (&items[6], &items[7]) {
+            (EntityOrHeader::Entity(dev), EntityOrHeader::Entity(ops)) => (dev, ops),
+            _ => unreachable!(),
+        };
+        assert!(matches!(
+            resolve_skills(dev, &items, &inputs)[..],
+            [Err(PersonaError::EntityNotFound(_))]
+        ));
+        assert!(matches!(
+            resolve_skills(ops, &items, &inputs)[..],
+            [Err(PersonaError::AmbiguousEntity { .. })]
+        ));
+        assert!(bundle(items, &inputs, "dev").is_err());
+    }
+}
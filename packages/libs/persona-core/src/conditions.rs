@@ -0,0 +1,370 @@
+//! Conditional inclusion: the `when` frontmatter block, evaluated against the machine
+//! running `persona` or against assumptions given with `--assume`.
+//!
+//! ```yaml
+//! when:
+//!   binary: jj            # every listed binary is on PATH
+//!   file: .jj             # every listed path exists, relative to the working directory
+//!   env: [CI]             # every listed environment variable is set and not empty
+//!   os: [linux, macos]    # any listed OS or OS family (`unix`, `windows`)
+//! ```
+use crate::{Diagnostic, EntityOrHeader, retain_entities};
+use persona_parser::ParsedEntity;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionKind {
+    Binary,
+    File,
+    Env,
+    Os,
+}
+
+impl ConditionKind {
+    const ALL: [(ConditionKind, &'static str); 4] = [
+        (ConditionKind::Binary, "binary"),
+        (ConditionKind::File, "file"),
+        (ConditionKind::Env, "env"),
+        (ConditionKind::Os, "os"),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(kind, _)| *kind == self)
+            .map_or("", |(_, name)| name)
+    }
+}
+
+/// The `when` block of an entity.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct When {
+    #[serde(default, deserialize_with = "persona_parser::one_or_many")]
+    binary: Vec<String>,
+    #[serde(default, deserialize_with = "persona_parser::one_or_many")]
+    file: Vec<String>,
+    #[serde(default, deserialize_with = "persona_parser::one_or_many")]
+    env: Vec<String>,
+    #[serde(default, deserialize_with = "persona_parser::one_or_many")]
+    os: Vec<String>,
+}
+
+impl When {
+    /// The `when` block of `entity`, if it has one.
+    pub fn of(entity: &ParsedEntity) -> Result<Option<Self>, serde_yaml::Error> {
+        match &entity.frontmatter.when {
+            None | Some(serde_yaml::Value::Null) => Ok(None),
+            Some(value) => serde_yaml::from_value(value.clone()).map(Some),
+        }
+    }
+
+    fn values(&self, kind: ConditionKind) -> &[String] {
+        match kind {
+            ConditionKind::Binary => &self.binary,
+            ConditionKind::File => &self.file,
+            ConditionKind::Env => &self.env,
+            ConditionKind::Os => &self.os,
+        }
+    }
+}
+
+/// Reports a `when` block that does not follow the schema above.
+pub fn invalid_conditions(entity: &ParsedEntity) -> Option<Diagnostic> {
+    let err = When::of(entity).err()?;
+    Some(
+        Diagnostic::error(
+            "invalid-condition",
+            format!(
+                "'{}' has an invalid `when`: {}",
+                entity.frontmatter.name, err
+            ),
+        )
+        .at(&entity.path, None),
+    )
+}
+
+/// A condition forced to hold or not, e.g. `binary:jj` or `env:CI=false`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assumption {
+    pub kind: ConditionKind,
+    pub value: String,
+    pub holds: bool,
+}
+
+impl FromStr for Assumption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (condition, holds) = match s.rsplit_once('=') {
+            Some((condition, "true")) => (condition, true),
+            Some((condition, "false")) => (condition, false),
+            Some((_, other)) => {
+                return Err(format!(
+                    "expected `true` or `false` after '=', got '{}'",
+                    other
+                ));
+            }
+            None => (s, true),
+        };
+        let (kind, value) = condition
+            .split_once(':')
+            .filter(|(_, value)| !value.is_empty())
+            .ok_or_else(|| format!("expected KIND:VALUE[=true|false], got '{}'", s))?;
+        let kind = ConditionKind::ALL
+            .iter()
+            .find(|(_, name)| *name == kind)
+            .map(|(kind, _)| *kind)
+            .ok_or_else(|| {
+                format!(
+                    "unknown condition '{}', expected binary, file, env or os",
+                    kind
+                )
+            })?;
+        Ok(Self {
+            kind,
+            value: value.to_string(),
+            holds,
+        })
+    }
+}
+
+impl fmt::Display for Assumption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}={}", self.kind.name(), self.value, self.holds)
+    }
+}
+
+/// Evaluates `when` blocks. Assumptions take precedence over the actual machine; assuming
+/// any OS to hold makes the assumed ones the only matching OS names.
+#[derive(Debug, Default)]
+pub struct Environment {
+    assumptions: Vec<Assumption>,
+}
+
+impl Environment {
+    pub fn new(assumptions: Vec<Assumption>) -> Self {
+        Self { assumptions }
+    }
+
+    /// Whether the condition holds, and whether that was assumed.
+    fn holds(&self, kind: ConditionKind, value: &str) -> (bool, bool) {
+        let assumed = self
+            .assumptions
+            .iter()
+            .rfind(|a| a.kind == kind && a.value == value);
+        if let Some(assumption) = assumed {
+            return (assumption.holds, true);
+        }
+        let holds = match kind {
+            ConditionKind::Binary => on_path(value),
+            ConditionKind::File => Path::new(value).exists(),
+            ConditionKind::Env => std::env::var_os(value).is_some_and(|v| !v.is_empty()),
+            ConditionKind::Os => {
+                let os_assumed = self
+                    .assumptions
+                    .iter()
+                    .any(|a| a.kind == ConditionKind::Os && a.holds);
+                if os_assumed {
+                    return (false, true);
+                }
+                value == std::env::consts::OS || value == std::env::consts::FAMILY
+            }
+        };
+        (holds, false)
+    }
+
+    /// Why `entity` is excluded: one reason per unmet condition, empty when it is included.
+    pub fn unmet(&self, entity: &ParsedEntity) -> Vec<String> {
+        let Ok(Some(when)) = When::of(entity) else {
+            return Vec::new();
+        };
+        let suffix = |assumed: bool| if assumed { " (assumed)" } else { "" };
+        let mut reasons = Vec::new();
+        for kind in [
+            ConditionKind::Binary,
+            ConditionKind::File,
+            ConditionKind::Env,
+        ] {
+            for value in when.values(kind) {
+                let (holds, assumed) = self.holds(kind, value);
+                if !holds {
+                    let reason = match kind {
+                        ConditionKind::Binary => format!("'{}' is not on PATH", value),
+                        ConditionKind::File => format!("'{}' does not exist", value),
+                        _ => format!("environment variable '{}' is not set", value),
+                    };
+                    reasons.push(format!("{}{}", reason, suffix(assumed)));
+                }
+            }
+        }
+        let os = when.values(ConditionKind::Os);
+        if !os.is_empty() {
+            let results: Vec<_> = os
+                .iter()
+                .map(|value| self.holds(ConditionKind::Os, value))
+                .collect();
+            if !results.iter().any(|(holds, _)| *holds) {
+                let assumed = results.iter().any(|(_, assumed)| *assumed);
+                reasons.push(format!("OS is not {}{}", os.join(" or "), suffix(assumed)));
+            }
+        }
+        reasons
+    }
+
+    /// Keeps the entities whose conditions hold and the headers of categories that still
+    /// contain one.
+    pub fn apply(&self, items: Vec<EntityOrHeader>) -> Vec<EntityOrHeader> {
+        retain_entities(items, |e| self.unmet(e).is_empty())
+    }
+}
+
+/// True when an executable file named `binary` is in a directory on `PATH`.
+fn on_path(binary: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| in_dirs(binary, std::env::split_paths(&path)))
+}
+
+/// Whether an executable `binary` (with the platform's executable suffix, if any) is in
+/// one of `dirs`.
+fn in_dirs(binary: &str, dirs: impl IntoIterator<Item = PathBuf>) -> bool {
+    dirs.into_iter().any(|dir| {
+        is_executable(&dir.join(binary))
+            || (!std::env::consts::EXE_SUFFIX.is_empty()
+                && is_executable(&dir.join(format!("{}{}", binary, std::env::consts::EXE_SUFFIX))))
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(fields: &str) -> ParsedEntity {
+        let mut frontmatter: serde_yaml::Mapping = serde_yaml::from_str(fields).unwrap();
+        frontmatter.insert("name".into(), "jj".into());
+        frontmatter.insert("description".into(), "d".into());
+        let frontmatter: Frontmatter =
+            serde_yaml::from_value(serde_yaml::Value::Mapping(frontmatter)).unwrap();
+        ParsedEntity {
+            path: PathBuf::from("/in/skills/jj/SKILL.md"),
+            frontmatter,
+            body: "b".to_string(),
+            char_count: 0,
+        }
+    }
+
+    fn environment(assumptions: &[&str]) -> Environment {
+        Environment::new(assumptions.iter().map(|a| a.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn test_parse_assumption() {
+        assert_eq!(
+            "binary:jj".parse(),
+            Ok(Assumption {
+                kind: ConditionKind::Binary,
+                value: "jj".to_string(),
+                holds: true,
+            })
+        );
+        let env: Assumption = "env:CI=false".parse().unwrap();
+        assert!(!env.holds);
+        assert_eq!(env.to_string(), "env:CI=false");
+        assert!("jj".parse::<Assumption>().is_err());
+        assert!("binary:".parse::<Assumption>().is_err());
+        assert!("shell:zsh".parse::<Assumption>().is_err());
+        assert!("env:CI=yes".parse::<Assumption>().is_err());
+    }
+
+    #[test]
+    fn test_unmet_conditions() {
+        let jj = entity("when: {binary: persona-missing-binary, env: [PERSONA_UNSET_VAR]}");
+        assert_eq!(
+            environment(&[]).unmet(&jj),
+            vec![
+                "'persona-missing-binary' is not on PATH",
+                "environment variable 'PERSONA_UNSET_VAR' is not set",
+            ]
+        );
+        assert!(
+            environment(&["binary:persona-missing-binary", "env:PERSONA_UNSET_VAR"])
+                .unmet(&jj)
+                .is_empty()
+        );
+
+        let file = entity("when: {file: Cargo.toml}");
+        assert_eq!(
+            environment(&["file:Cargo.toml=false"]).unmet(&file),
+            vec!["'Cargo.toml' does not exist (assumed)"]
+        );
+        assert!(environment(&[]).unmet(&entity("{}")).is_empty());
+    }
+
+    #[test]
+    fn test_os_assumptions() {
+        let unix = entity("when: {os: [linux, macos]}");
+        assert!(environment(&["os:macos"]).unmet(&unix).is_empty());
+        assert_eq!(
+            environment(&["os:windows"]).unmet(&unix),
+            vec!["OS is not linux or macos (assumed)"]
+        );
+        assert!(
+            !environment(&["os:linux=false", "os:macos=false"])
+                .unmet(&unix)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_invalid_conditions() {
+        assert!(invalid_conditions(&entity("when: {binary: [jj, git]}")).is_none());
+        assert_eq!(
+            invalid_conditions(&entity("when: {shell: zsh}"))
+                .unwrap()
+                .rule,
+            "invalid-condition"
+        );
+        assert!(invalid_conditions(&entity("when: jj")).is_some());
+        assert!(invalid_conditions(&entity("when: {binary: [1]}")).is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_dirs_needs_exec_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("persona_test_on_path");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("jj")).unwrap();
+        for (name, mode) in [("tool", 0o755), ("notes", 0o644)] {
+            let file = dir.join(name);
+            std::fs::write(&file, "").unwrap();
+            std::fs::set_permissions(&file, std::fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let dirs = || vec![PathBuf::from("/nonexistent"), dir.clone()];
+        assert!(in_dirs("tool", dirs()));
+        assert!(!in_dirs("notes", dirs()));
+        // A directory is not a binary, whatever its permissions.
+        assert!(!in_dirs("jj", dirs()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+//! Project configuration, read from `persona.yaml` in the working directory or the file
+//! given with `--config`.
+//!
+//! ```yaml
+//! profiles:
+//!   backend:
+//!     tags: [backend, shared]
+//!     exclude_tags: [experimental]
+//!     output: docs/AGENTS.backend.md   # defaults to AGENTS.<profile>.md
+//! duplicate_names: error               # or warn (default), allow-namespaced
+//! ```
+use crate::PersonaError;
+use crate::names::DuplicateNames;
+use crate::tags::TagFilter;
+use persona_parser::is_valid_name;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "persona.yaml";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named variants of the agents file, each built from a subset of entities.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// How entities sharing a name are reported.
+    #[serde(default)]
+    pub duplicate_names: DuplicateNames,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Entities carrying any of these tags are included; all entities when empty.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Entities carrying any of these tags are left out.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// File the profile is written to.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+impl Profile {
+    pub fn tag_filter(&self) -> TagFilter {
+        TagFilter::new(self.tags.clone(), self.exclude_tags.clone())
+    }
+
+    /// Where the profile named `name` is written.
+    pub fn output(&self, name: &str) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("AGENTS.{}.md", name)))
+    }
+}
+
+impl Config {
+    /// Reads `path`, or [`CONFIG_FILE`] when no path is given. A missing default file is
+    /// an empty configuration; a missing explicit file is an error.
+    pub fn load(path: Option<&Path>) -> Result<Self, PersonaError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(CONFIG_FILE).exists() => Path::new(CONFIG_FILE),
+            None => return Ok(Self::default()),
+        };
+        Self::parse(&std::fs::read_to_string(path)?, path)
+    }
+
+    fn parse(content: &str, path: &Path) -> Result<Self, PersonaError> {
+        let invalid = |message: String| PersonaError::Config {
+            path: path.display().to_string(),
+            message,
+        };
+        let config: Self = if content.trim().is_empty() {
+            Self::default()
+        } else {
+            serde_yaml::from_str(content).map_err(|e| invalid(e.to_string()))?
+        };
+        if let Some(name) = config.profiles.keys().find(|name| !is_valid_name(name)) {
+            return Err(invalid(format!(
+                "profile name '{}' must be lowercase alphanumeric with hyphens",
+                name
+            )));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let path = Path::new(CONFIG_FILE);
+        let config = Config::parse(
+            "profiles:\n  backend:\n    tags: [backend]\n    exclude_tags: [ui]\n  frontend:\n    output: web/AGENTS.md\n",
+            path,
+        )
+        .unwrap();
+
+        let backend = &config.profiles["backend"];
+        assert_eq!(
+            backend.tag_filter(),
+            TagFilter::new(vec!["backend".to_string()], vec!["ui".to_string()])
+        );
+        assert_eq!(
+            backend.output("backend"),
+            PathBuf::from("AGENTS.backend.md")
+        );
+        assert_eq!(
+            config.profiles["frontend"].output("frontend"),
+            PathBuf::from("web/AGENTS.md")
+        );
+
+        assert_eq!(config.duplicate_names, DuplicateNames::Warn);
+        let strict = Config::parse("duplicate_names: allow-namespaced", path).unwrap();
+        assert_eq!(strict.duplicate_names, DuplicateNames::AllowNamespaced);
+        assert!(Config::parse("duplicate_names: ignore", path).is_err());
+
+        assert_eq!(Config::parse("", path).unwrap(), Config::default());
+        assert!(matches!(
+            Config::parse("profile: {}", path),
+            Err(PersonaError::Config { .. })
+        ));
+        assert!(matches!(
+            Config::parse("profiles:\n  Back End: {}", path),
+            Err(PersonaError::Config { .. })
+        ));
+    }
+}
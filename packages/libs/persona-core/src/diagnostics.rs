@@ -0,0 +1,205 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is. Errors fail `check`; warnings are only reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Every rule a diagnostic can be reported under, with a short description.
+pub const RULES: &[(&str, &str)] = &[
+    ("missing-input", "Input directory does not exist"),
+    ("io-error", "File could not be read or written"),
+    ("invalid-filename", "Entity filename is not ALL CAPS"),
+    ("missing-frontmatter", "Entity has no YAML frontmatter"),
+    ("invalid-yaml", "Frontmatter is not valid YAML"),
+    ("invalid-name", "Entity name has an invalid format"),
+    ("name-mismatch", "Entity name does not match its directory"),
+    (
+        "empty-description",
+        "Entity description is missing or empty",
+    ),
+    ("empty-body", "Entity body is missing or empty"),
+    (
+        "invalid-frontmatter-key",
+        "Frontmatter key is not a plain scalar",
+    ),
+    (
+        "unsupported-frontmatter-value",
+        "Frontmatter value uses an unsupported YAML tag",
+    ),
+    (
+        "unknown-requirement",
+        "Required entity does not exist or is ambiguous",
+    ),
+    ("requirement-cycle", "Entity requirements form a cycle"),
+    (
+        "deprecated-requirement",
+        "Entity requires a deprecated entity",
+    ),
+    (
+        "unknown-skill",
+        "Persona lists a skill that does not exist or is ambiguous",
+    ),
+    (
+        "layer-conflict",
+        "Entity is defined in several inputs without a layer directive",
+    ),
+    (
+        "layer-target-missing",
+        "Layer directive without an entity to apply it to in an earlier input",
+    ),
+    (
+        "duplicate-name",
+        "Entity name is used by more than one entity",
+    ),
+    (
+        "invalid-condition",
+        "Entity `when` block does not follow the condition schema",
+    ),
+    ("token-limit", "File exceeds the token error limit"),
+    ("token-warning", "File exceeds the token warning limit"),
+    ("agents-file-missing", "Agents file has not been generated"),
+    ("agents-file-stale", "Agents file does not match the inputs"),
+];
+
+/// A single finding from parsing, validating or checking the inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Rule identifier from [`RULES`].
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    /// 1-based line in `path`, when known.
+    pub line: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn error(rule: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            rule,
+            severity: Severity::Error,
+            message: message.into(),
+            path: None,
+            line: None,
+        }
+    }
+
+    pub fn warning(rule: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(rule, message)
+        }
+    }
+
+    /// Attaches a file location.
+    pub fn at(mut self, path: &Path, line: Option<usize>) -> Self {
+        self.path = Some(path.to_path_buf());
+        self.line = line;
+        self
+    }
+
+    /// Reports a parse or validation failure of `path`.
+    pub fn from_parse_error(path: &Path, error: &persona_parser::PersonaError) -> Self {
+        use persona_parser::PersonaError as E;
+        let (rule, line) = match error {
+            E::Io(_) => ("io-error", None),
+            E::Yaml(e) => ("invalid-yaml", e.location().map(|l| l.line())),
+            E::InvalidFilename(_) => ("invalid-filename", None),
+            E::MissingFrontmatter => ("missing-frontmatter", Some(1)),
+            E::NameMismatch { .. } => ("name-mismatch", None),
+            E::InvalidNameFormat(_) => ("invalid-name", None),
+            E::EmptyDescription => ("empty-description", None),
+            E::EmptyBody => ("empty-body", None),
+            E::ParentDirNotFound(_) => ("io-error", None),
+            E::InvalidFrontmatterKey(_) => ("invalid-frontmatter-key", None),
+            E::UnsupportedFrontmatterValue(_) => ("unsupported-frontmatter-value", None),
+        };
+        Self::error(rule, error.to_string()).at(path, line)
+    }
+
+    /// Checks `tokens` against the budget, returning a diagnostic when a limit is exceeded.
+    pub fn token_budget(label: &str, tokens: u64, warn: u64, error: u64) -> Option<Self> {
+        if tokens > error {
+            Some(Self::error(
+                "token-limit",
+                format!(
+                    "{} exceeds error limit of {} tokens (has {})",
+                    label, error, tokens
+                ),
+            ))
+        } else if tokens > warn {
+            Some(Self::warning(
+                "token-warning",
+                format!(
+                    "{} exceeds warning limit of {} tokens (has {})",
+                    label, warn, tokens
+                ),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.path, self.line) {
+            (Some(path), Some(line)) => write!(f, "{}:{}: ", path.display(), line)?,
+            (Some(path), None) => write!(f, "{}: ", path.display())?,
+            _ => {}
+        }
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_parse_error_yaml_line() {
+        let content = "---\nname: test\ndescription: [unclosed\n---\nBody";
+        let (frontmatter, _) = persona_parser::extract_frontmatter_and_body(content).unwrap();
+        let error: persona_parser::PersonaError =
+            serde_yaml::from_str::<serde_yaml::Value>(frontmatter)
+                .unwrap_err()
+                .into();
+
+        let diagnostic = Diagnostic::from_parse_error(Path::new("TEST.md"), &error);
+
+        assert_eq!(diagnostic.rule, "invalid-yaml");
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert!(diagnostic.line.is_some_and(|l| l >= 3));
+        assert!(RULES.iter().any(|(id, _)| *id == diagnostic.rule));
+    }
+
+    #[test]
+    fn test_token_budget() {
+        assert_eq!(Diagnostic::token_budget("A", 10, 20, 30), None);
+
+        let warning = Diagnostic::token_budget("A", 25, 20, 30).unwrap();
+        assert_eq!(warning.rule, "token-warning");
+        assert_eq!(warning.severity, Severity::Warning);
+
+        let error = Diagnostic::token_budget("A", 35, 20, 30).unwrap();
+        assert_eq!(error.rule, "token-limit");
+        assert_eq!(error.message, "A exceeds error limit of 30 tokens (has 35)");
+        assert_eq!(
+            error.at(Path::new("A.md"), Some(1)).to_string(),
+            "A.md:1: A exceeds error limit of 30 tokens (has 35) [token-limit]"
+        );
+    }
+}
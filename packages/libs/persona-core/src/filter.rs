@@ -0,0 +1,447 @@
+//! Filter expressions for selecting entities by their frontmatter and location.
+//!
+//! ```text
+//! expr       := or
+//! or         := and ("or" and)*
+//! and        := unary ("and" unary)*
+//! unary      := "not" unary | "(" expr ")" | "exists(" field ")" | comparison
+//! comparison := field ("=" | "!=" | "contains" | "~" | "matches") value
+//! ```
+//!
+//! Fields are frontmatter keys (nested keys joined with `.`) plus `kind`, `path` (the
+//! definition file relative to its input) and `category` (the directories between the
+//! input and the entity). Values are bare words or quoted strings. `=` and `!=` compare
+//! exactly, `contains` (or `~`) is a case-insensitive substring match and `matches` is
+//! a glob where `*` stays within a path segment and `**` crosses them. A comparison on a
+//! list is true when any element satisfies it; comparisons on missing fields are false.
+use crate::resolve::input_for;
+use crate::{EntityOrHeader, PersonaError, retain_entities};
+use globset::{GlobBuilder, GlobMatcher};
+use persona_parser::ParsedEntity;
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(String),
+    Compare {
+        field: String,
+        op: Op,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Matches(GlobMatcher),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+/// A parsed filter expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Self, PersonaError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(invalid(format!("unexpected {}", describe(token))));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, entity: &ParsedEntity, inputs: &[PathBuf]) -> bool {
+        let fields = Fields::new(entity, inputs);
+        eval(&self.expr, &fields)
+    }
+
+    /// Keeps the entities matching the filter, and the headers of categories that still
+    /// contain one of them.
+    pub fn apply(&self, items: Vec<EntityOrHeader>, inputs: &[PathBuf]) -> Vec<EntityOrHeader> {
+        retain_entities(items, |e| self.matches(e, inputs))
+    }
+}
+
+fn invalid(message: String) -> PersonaError {
+    PersonaError::InvalidQuery(message)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) => format!("'{}'", w),
+        Token::Quoted(q) => format!("\"{}\"", q),
+        Token::Symbol(s) => format!("'{}'", s),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, PersonaError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '~' | '=' => {
+                chars.next();
+                tokens.push(Token::Symbol(match c {
+                    '(' => "(",
+                    ')' => ")",
+                    '~' => "~",
+                    _ => "=",
+                }));
+            }
+            '!' => {
+                chars.next();
+                if chars.next_if_eq(&'=').is_some() {
+                    tokens.push(Token::Symbol("!="));
+                } else {
+                    tokens.push(Token::Word("not".to_string()));
+                }
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some('\\') => value.extend(chars.next()),
+                        Some(ch) => value.push(ch),
+                        None => return Err(invalid("unterminated string".to_string())),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || "()~=!\"'".contains(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), PersonaError> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(invalid(format!(
+                "expected '{}', found {}",
+                symbol,
+                describe(&token)
+            ))),
+            None => Err(invalid(format!("expected '{}'", symbol))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, PersonaError> {
+        let mut expr = self.and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, PersonaError> {
+        let mut expr = self.unary()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, PersonaError> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.tokens.get(self.pos) == Some(&Token::Symbol("(")) {
+            self.pos += 1;
+            let expr = self.or()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if self.peek_keyword("exists") && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("("))
+        {
+            self.pos += 2;
+            let field = self.field()?;
+            self.expect(")")?;
+            return Ok(Expr::Exists(field));
+        }
+        self.comparison()
+    }
+
+    fn field(&mut self) -> Result<String, PersonaError> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => Ok(w),
+            Some(token) => Err(invalid(format!(
+                "expected a field, found {}",
+                describe(&token)
+            ))),
+            None => Err(invalid("expected a field".to_string())),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, PersonaError> {
+        let field = self.field()?;
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => "=",
+            Some(Token::Symbol("!=")) => "!=",
+            Some(Token::Symbol("~")) => "contains",
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("contains") => "contains",
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("matches") => "matches",
+            Some(token) => {
+                return Err(invalid(format!(
+                    "expected an operator after '{}', found {}",
+                    field,
+                    describe(&token)
+                )));
+            }
+            None => {
+                return Err(invalid(format!("expected an operator after '{}'", field)));
+            }
+        };
+        let value = match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => w,
+            _ => return Err(invalid(format!("expected a value after '{}'", op))),
+        };
+        let op = match op {
+            "=" => Op::Eq,
+            "!=" => Op::Ne,
+            "contains" => Op::Contains,
+            _ => Op::Matches(
+                GlobBuilder::new(&value)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| invalid(e.to_string()))?
+                    .compile_matcher(),
+            ),
+        };
+        Ok(Expr::Compare { field, op, value })
+    }
+}
+
+/// Field values of one entity.
+struct Fields<'a> {
+    entity: &'a ParsedEntity,
+    path: String,
+    category: String,
+}
+
+impl<'a> Fields<'a> {
+    fn new(entity: &'a ParsedEntity, inputs: &[PathBuf]) -> Self {
+        let relative = input_for(&entity.path, inputs)
+            .and_then(|input| entity.path.strip_prefix(input).ok())
+            .unwrap_or(&entity.path);
+        let category = relative
+            .parent()
+            .and_then(Path::parent)
+            .map(slashed)
+            .unwrap_or_default();
+        Self {
+            entity,
+            path: slashed(relative),
+            category,
+        }
+    }
+
+    /// Scalar values of `field`; lists yield each element.
+    fn values(&self, field: &str) -> Option<Vec<String>> {
+        let frontmatter = &self.entity.frontmatter;
+        let value = match field {
+            "name" => return Some(vec![frontmatter.name.clone()]),
+            "description" => return Some(vec![frontmatter.description.clone()]),
+            "kind" => return Some(vec![self.entity.kind()]),
+            "path" => return Some(vec![self.path.clone()]),
+            "category" => return Some(vec![self.category.clone()]),
+            "inline" => return frontmatter.inline.map(|v| vec![v.to_string()]),
+            "order" | "priority" => return frontmatter.order.map(|v| vec![v.to_string()]),
+            "requires" if frontmatter.requires.is_empty() => return None,
+            "requires" => return Some(frontmatter.requires.clone()),
+            "skills" if frontmatter.skills.is_empty() => return None,
+            "skills" => return Some(frontmatter.skills.clone()),
+            "tags" if frontmatter.tags.is_empty() => return None,
+            "tags" => return Some(frontmatter.tags.clone()),
+            "deprecated" => return Some(vec![frontmatter.deprecated.to_string()]),
+            // `when` is kept apart from the other keys but filters like them
+            _ => match field.split_once('.') {
+                Some(("when", keys)) => keys
+                    .split('.')
+                    .try_fold(frontmatter.when.as_ref()?, |value, key| value.get(key))?,
+                _ if field == "when" => frontmatter.when.as_ref()?,
+                _ => field
+                    .split('.')
+                    .try_fold(&frontmatter.other, |value, key| value.get(key))?,
+            },
+        };
+        match value {
+            Value::Null => None,
+            Value::Sequence(items) => Some(items.iter().filter_map(scalar).collect()),
+            other => Some(scalar(other).into_iter().collect()),
+        }
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn eval(expr: &Expr, fields: &Fields) -> bool {
+    match expr {
+        Expr::Or(a, b) => eval(a, fields) || eval(b, fields),
+        Expr::And(a, b) => eval(a, fields) && eval(b, fields),
+        Expr::Not(e) => !eval(e, fields),
+        Expr::Exists(field) => fields.values(field).is_some(),
+        Expr::Compare { field, op, value } => {
+            let Some(values) = fields.values(field) else {
+                return false;
+            };
+            match op {
+                Op::Eq => values.iter().any(|v| v == value),
+                Op::Ne => !values.iter().any(|v| v == value),
+                Op::Contains => {
+                    let needle = value.to_lowercase();
+                    values.iter().any(|v| v.to_lowercase().contains(&needle))
+                }
+                Op::Matches(glob) => values.iter().any(|v| glob.is_match(v)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(path: &str, name: &str, fields: &str) -> ParsedEntity {
+        let yaml = format!("name: {name}\ndescription: desc\n{fields}");
+        ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: serde_yaml::from_str::<Frontmatter>(&yaml).unwrap(),
+            body: "body".to_string(),
+            char_count: 0,
+        }
+    }
+
+    fn matching(expr: &str) -> Vec<String> {
+        let inputs = vec![PathBuf::from("/in")];
+        let entities = [
+            entity(
+                "/in/skills/vcs/jj-staging/SKILL.md",
+                "jj-staging",
+                "compatibility: Requires JJ 0.20\nmetadata:\n  owner: tools",
+            ),
+            entity(
+                "/in/skills/vcs/git/SKILL.md",
+                "git",
+                "compatibility: [git, jj]\nlicense: MIT",
+            ),
+            entity("/in/personas/qa/PERSONA.md", "qa", "tags: [testing]"),
+        ];
+        let filter = Filter::parse(expr).unwrap();
+        entities
+            .iter()
+            .filter(|e| filter.matches(e, &inputs))
+            .map(|e| e.frontmatter.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_expressions() {
+        assert_eq!(
+            matching("kind = skill and compatibility contains jj and not exists(license)"),
+            vec!["jj-staging"]
+        );
+        assert_eq!(matching("compatibility ~ JJ"), vec!["jj-staging", "git"]);
+        assert_eq!(matching("license != MIT"), Vec::<String>::new());
+        assert_eq!(matching("not license = MIT"), vec!["jj-staging", "qa"]);
+        assert_eq!(matching("metadata.owner = tools"), vec!["jj-staging"]);
+        assert_eq!(
+            matching("path matches 'skills/**'"),
+            vec!["jj-staging", "git"]
+        );
+        assert_eq!(
+            matching("path matches 'skills/*/SKILL.md'"),
+            Vec::<String>::new()
+        );
+        assert_eq!(matching("category = skills/vcs"), vec!["jj-staging", "git"]);
+        assert_eq!(
+            matching("(name = qa or name = git) and kind = persona"),
+            vec!["qa"]
+        );
+        assert_eq!(
+            matching("!exists(tags) and !(name = git)"),
+            vec!["jj-staging"]
+        );
+    }
+
+    #[test]
+    fn test_filter_parse_errors() {
+        for expr in [
+            "",
+            "name",
+            "name =",
+            "name = a and",
+            "(name = a",
+            "name = 'open",
+            "exists(name",
+            "name = a b",
+            "path matches '['",
+        ] {
+            assert!(
+                matches!(Filter::parse(expr), Err(PersonaError::InvalidQuery(_))),
+                "{expr}"
+            );
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! The category tree and `requires` edges between entities, rendered as Graphviz DOT or
+//! Mermaid flowcharts.
+use crate::requires::resolve_requirements;
+use crate::resolve::find_entity;
+use crate::tree::NodeRef;
+use crate::{EntityOrHeader, PersonaError};
+use persona_parser::ParsedEntity;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Fill colour of entities nothing requires, when highlighted.
+const ORPHAN_FILL: &str = "#fde2e2";
+const ORPHAN_STROKE: &str = "#c0392b";
+
+#[derive(Debug)]
+pub struct GraphNode<'a> {
+    pub entity: &'a ParsedEntity,
+    /// Entity directory relative to its input, with `/` separators.
+    pub id: String,
+    /// No entity requires this one.
+    pub orphan: bool,
+}
+
+#[derive(Debug, Default)]
+struct Cluster {
+    name: String,
+    id: String,
+    nodes: Vec<usize>,
+    children: Vec<Cluster>,
+}
+
+#[derive(Debug)]
+pub struct Graph<'a> {
+    pub nodes: Vec<GraphNode<'a>>,
+    /// Requirement edges as indices into `nodes`, from the requiring entity.
+    pub edges: Vec<(usize, usize)>,
+    root: Cluster,
+}
+
+impl<'a> Graph<'a> {
+    /// Builds the graph of every entity, or with `focus` only of that entity and
+    /// everything it transitively requires. Orphans are determined across all entities.
+    pub fn build(
+        items: &'a [EntityOrHeader],
+        inputs: &[PathBuf],
+        focus: Option<&str>,
+    ) -> Result<Self, PersonaError> {
+        let entities = items.iter().filter_map(|item| match item {
+            EntityOrHeader::Entity(e) => Some(e),
+            EntityOrHeader::Header(_) => None,
+        });
+        let mut required: HashSet<&Path> = HashSet::new();
+        let mut requirements: HashMap<&Path, Vec<&ParsedEntity>> = HashMap::new();
+        for entity in entities {
+            let resolved: Vec<&ParsedEntity> = resolve_requirements(entity, items, inputs)
+                .into_iter()
+                .filter_map(|(_, r)| r.ok())
+                .collect();
+            required.extend(resolved.iter().map(|r| r.path.as_path()));
+            requirements.insert(&entity.path, resolved);
+        }
+
+        let included = match focus {
+            Some(query) => {
+                let start = find_entity(items, inputs, query)?;
+                let mut seen = HashSet::from([start.path.as_path()]);
+                let mut queue = vec![start];
+                while let Some(entity) = queue.pop() {
+                    for required in requirements
+                        .get(entity.path.as_path())
+                        .into_iter()
+                        .flatten()
+                    {
+                        if seen.insert(&required.path) {
+                            queue.push(required);
+                        }
+                    }
+                }
+                Some(seen)
+            }
+            None => None,
+        };
+
+        let tree = NodeRef::build(items, inputs)?;
+        let mut nodes = Vec::new();
+        let mut root = Cluster::default();
+        add_children(&tree, "", &included, &required, &mut nodes, &mut root);
+
+        let index: HashMap<&Path, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.entity.path.as_path(), i))
+            .collect();
+        let mut edges = Vec::new();
+        for (from, node) in nodes.iter().enumerate() {
+            for required in requirements
+                .get(node.entity.path.as_path())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(&to) = index.get(required.path.as_path()) {
+                    edges.push((from, to));
+                }
+            }
+        }
+
+        Ok(Self { nodes, edges, root })
+    }
+
+    /// Renders a Graphviz `digraph` with one cluster per category.
+    pub fn to_dot(&self, highlight_orphans: bool) -> String {
+        let mut out = String::from("digraph persona {\n  rankdir=LR;\n  node [shape=box];\n");
+        self.write_dot_cluster(&mut out, &self.root, 1, highlight_orphans);
+        for (from, to) in &self.edges {
+            let _ = writeln!(
+                out,
+                "  {} -> {};",
+                dot_quote(&self.nodes[*from].id),
+                dot_quote(&self.nodes[*to].id)
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot_cluster(&self, out: &mut String, cluster: &Cluster, depth: usize, orphans: bool) {
+        let indent = "  ".repeat(depth);
+        for &i in &cluster.nodes {
+            let node = &self.nodes[i];
+            let style = if orphans && node.orphan {
+                format!(", style=filled, fillcolor=\"{}\"", ORPHAN_FILL)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                out,
+                "{}{} [label={}{}];",
+                indent,
+                dot_quote(&node.id),
+                dot_quote(&node.entity.frontmatter.name),
+                style
+            );
+        }
+        for child in &cluster.children {
+            let _ = writeln!(
+                out,
+                "{}subgraph {} {{",
+                indent,
+                dot_quote(&format!("cluster_{}", child.id))
+            );
+            let _ = writeln!(out, "{}  label={};", indent, dot_quote(&child.name));
+            self.write_dot_cluster(out, child, depth + 1, orphans);
+            let _ = writeln!(out, "{}}}", indent);
+        }
+    }
+
+    /// Renders a Mermaid flowchart with one subgraph per category. Mermaid identifiers
+    /// cannot hold arbitrary paths, so nodes are numbered in tree order.
+    pub fn to_mermaid(&self, highlight_orphans: bool) -> String {
+        let mut out = String::from("flowchart LR\n");
+        let mut clusters = 0;
+        self.write_mermaid_cluster(&mut out, &self.root, 1, &mut clusters);
+        for (from, to) in &self.edges {
+            let _ = writeln!(out, "  e{} --> e{}", from, to);
+        }
+        let orphans: Vec<String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.orphan)
+            .map(|(i, _)| format!("e{}", i))
+            .collect();
+        if highlight_orphans && !orphans.is_empty() {
+            let _ = writeln!(
+                out,
+                "  classDef orphan fill:{},stroke:{}",
+                ORPHAN_FILL, ORPHAN_STROKE
+            );
+            let _ = writeln!(out, "  class {} orphan", orphans.join(","));
+        }
+        out
+    }
+
+    fn write_mermaid_cluster(
+        &self,
+        out: &mut String,
+        cluster: &Cluster,
+        depth: usize,
+        clusters: &mut usize,
+    ) {
+        let indent = "  ".repeat(depth);
+        for &i in &cluster.nodes {
+            let name = &self.nodes[i].entity.frontmatter.name;
+            let _ = writeln!(out, "{}e{}[{}]", indent, i, mermaid_quote(name));
+        }
+        for child in &cluster.children {
+            let _ = writeln!(
+                out,
+                "{}subgraph c{}[{}]",
+                indent,
+                clusters,
+                mermaid_quote(&child.name)
+            );
+            *clusters += 1;
+            self.write_mermaid_cluster(out, child, depth + 1, clusters);
+            let _ = writeln!(out, "{}end", indent);
+        }
+    }
+}
+
+/// Adds the entities below `node` to `cluster`, in output order, with one sub-cluster per
+/// category directory that still holds an included entity.
+fn add_children<'a>(
+    node: &NodeRef<'a>,
+    prefix: &str,
+    included: &Option<HashSet<&Path>>,
+    required: &HashSet<&Path>,
+    nodes: &mut Vec<GraphNode<'a>>,
+    cluster: &mut Cluster,
+) {
+    for (name, child) in node.ordered_children() {
+        let id = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        if let Some(entity) = child.entity {
+            let wanted = included
+                .as_ref()
+                .is_none_or(|set| set.contains(entity.path.as_path()));
+            if wanted {
+                cluster.nodes.push(nodes.len());
+                nodes.push(GraphNode {
+                    entity,
+                    id: id.clone(),
+                    orphan: !required.contains(entity.path.as_path()),
+                });
+            }
+        }
+        let mut sub = Cluster {
+            name: name.clone(),
+            id: id.clone(),
+            ..Default::default()
+        };
+        add_children(child, &id, included, required, nodes, &mut sub);
+        if !sub.nodes.is_empty() || !sub.children.is_empty() {
+            cluster.children.push(sub);
+        }
+    }
+}
+
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn mermaid_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(path: &str, name: &str, requires: &[&str]) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                requires: requires.iter().map(|r| r.to_string()).collect(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity("/in/personas/dev/PERSONA.md", "dev", &["rust"]),
+            entity("/in/skills/lang/rust/SKILL.md", "rust", &["git"]),
+            entity("/in/skills/vcs/git/SKILL.md", "git", &[]),
+            entity("/in/skills/vcs/svn/SKILL.md", "svn", &[]),
+        ]
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let items = items();
+        let graph = Graph::build(&items, &[PathBuf::from("/in")], None).unwrap();
+        assert_eq!(
+            graph.to_dot(true),
+            r##"digraph persona {
+  rankdir=LR;
+  node [shape=box];
+  subgraph "cluster_personas" {
+    label="personas";
+    "personas/dev" [label="dev", style=filled, fillcolor="#fde2e2"];
+  }
+  subgraph "cluster_skills" {
+    label="skills";
+    subgraph "cluster_skills/lang" {
+      label="lang";
+      "skills/lang/rust" [label="rust"];
+    }
+    subgraph "cluster_skills/vcs" {
+      label="vcs";
+      "skills/vcs/git" [label="git"];
+      "skills/vcs/svn" [label="svn", style=filled, fillcolor="#fde2e2"];
+    }
+  }
+  "personas/dev" -> "skills/lang/rust";
+  "skills/lang/rust" -> "skills/vcs/git";
+}
+"##
+        );
+    }
+
+    #[test]
+    fn test_graph_mermaid_focus() {
+        let items = items();
+        let graph = Graph::build(&items, &[PathBuf::from("/in")], Some("rust")).unwrap();
+        assert_eq!(
+            graph.to_mermaid(true),
+            r#"flowchart LR
+  subgraph c0["skills"]
+    subgraph c1["lang"]
+      e0["rust"]
+    end
+    subgraph c2["vcs"]
+      e1["git"]
+    end
+  end
+  e0 --> e1
+"#
+        );
+
+        assert!(matches!(
+            Graph::build(&items, &[PathBuf::from("/in")], Some("python")),
+            Err(PersonaError::EntityNotFound(_))
+        ));
+    }
+}
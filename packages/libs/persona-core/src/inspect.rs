@@ -0,0 +1,131 @@
+use crate::PersonaError;
+use crate::resolve::input_for;
+use persona_parser::ParsedEntity;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Everything known about a single parsed entity, for `persona show`.
+#[derive(Debug, Serialize)]
+pub struct EntityDetails {
+    pub name: String,
+    pub kind: String,
+    pub path: PathBuf,
+    /// Input directory the entity was found in.
+    pub input: Option<PathBuf>,
+    /// Directories between the input and the entity directory.
+    pub category: Vec<String>,
+    /// The frontmatter exactly as written, including keys the tool does not interpret.
+    pub frontmatter: serde_yaml::Mapping,
+    /// Approximate tokens of the whole definition file.
+    pub file_tokens: usize,
+    /// Approximate tokens of the body alone.
+    pub body_tokens: usize,
+    /// Other files in the entity directory, relative to it. Nested entities are skipped.
+    pub assets: Vec<PathBuf>,
+    pub body: String,
+}
+
+impl EntityDetails {
+    pub fn new(entity: &ParsedEntity, inputs: &[PathBuf]) -> Result<Self, PersonaError> {
+        let content = std::fs::read_to_string(&entity.path)?;
+        let (frontmatter, _) = persona_parser::extract_frontmatter_and_body(&content)?;
+        let frontmatter: serde_yaml::Mapping =
+            serde_yaml::from_str(frontmatter).map_err(persona_parser::PersonaError::from)?;
+
+        let dir = entity.path.parent().unwrap_or(Path::new("."));
+        let input = input_for(dir, inputs).cloned();
+        let category = input
+            .as_ref()
+            .and_then(|input| dir.strip_prefix(input).ok())
+            .and_then(|rel| rel.parent())
+            .map(|rel| {
+                rel.iter()
+                    .map(|c| c.to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            name: entity.frontmatter.name.clone(),
+            kind: entity.kind(),
+            path: entity.path.clone(),
+            input,
+            category,
+            frontmatter,
+            file_tokens: entity.char_count / 5,
+            body_tokens: entity.body_tokens(),
+            assets: assets(dir, &entity.path),
+            body: entity.body.clone(),
+        })
+    }
+}
+
+fn assets(dir: &Path, definition: &Path) -> Vec<PathBuf> {
+    let mut assets: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !(e.file_type().is_dir() && is_entity_dir(e.path())))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path() != definition)
+        .filter_map(|e| e.path().strip_prefix(dir).ok().map(Path::to_path_buf))
+        .collect();
+    assets.sort();
+    assets
+}
+
+/// A directory holding an ALL-CAPS definition file other than `HEADER.md`.
+fn is_entity_dir(dir: &Path) -> bool {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries.filter_map(|e| e.ok()).any(|e| {
+                let path = e.path();
+                let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                path.extension().is_some_and(|ext| ext == "md")
+                    && stem != "HEADER"
+                    && !stem.is_empty()
+                    && !stem.chars().any(|c| c.is_lowercase())
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::{MarkdownParser, PersonaParser as _};
+    use std::fs;
+
+    #[test]
+    fn test_entity_details() {
+        let input = std::env::temp_dir().join("persona_test_inspect");
+        if input.exists() {
+            fs::remove_dir_all(&input).unwrap();
+        }
+        let dir = input.join("skills/coding/rust");
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(
+            dir.join("SKILL.md"),
+            "---\nname: rust\ndescription: Rust skill\nlicense: MIT\n---\nUse rust for systems programming.",
+        )
+        .unwrap();
+        fs::write(dir.join("scripts/check.sh"), "cargo check").unwrap();
+        fs::write(
+            dir.join("nested/NESTED.md"),
+            "---\nname: nested\ndescription: d\n---\nb",
+        )
+        .unwrap();
+
+        let entity = MarkdownParser.parse(&dir.join("SKILL.md")).unwrap();
+        let details = EntityDetails::new(&entity, std::slice::from_ref(&input)).unwrap();
+
+        assert_eq!(details.kind, "skill");
+        assert_eq!(details.input, Some(input.clone()));
+        assert_eq!(details.category, vec!["skills", "coding"]);
+        assert_eq!(details.frontmatter.get("license").unwrap(), "MIT");
+        assert_eq!(details.body_tokens, 6);
+        assert_eq!(details.assets, vec![PathBuf::from("scripts/check.sh")]);
+
+        fs::remove_dir_all(input).unwrap();
+    }
+}
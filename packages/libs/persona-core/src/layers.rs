@@ -0,0 +1,389 @@
+//! Layered inputs: later `-i` inputs may override, extend or remove the entity at the same
+//! path in earlier inputs with the `layer` frontmatter directive. Category headers of a
+//! later input replace earlier ones.
+use crate::{Diagnostic, EntityOrHeader};
+use persona_parser::{Frontmatter, Layer, ParsedEntity};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The inputs that contributed to an entity, in order: the input defining it first, then
+/// every later input that overrode or extended it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Origin {
+    pub layers: Vec<(PathBuf, Option<Layer>)>,
+    /// The definition file of each of those layers, in the same order.
+    pub files: Vec<PathBuf>,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (input, layer)) in self.layers.iter().enumerate() {
+            let verb = match layer {
+                _ if i == 0 => "",
+                Some(Layer::Override) => ", overridden by ",
+                Some(Layer::Extend) => ", extended by ",
+                Some(Layer::Remove) | None => ", then ",
+            };
+            write!(f, "{}{}", verb, input.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Items left after applying every layer, with the origin of each entity by path.
+#[derive(Debug, Default)]
+pub struct Layered {
+    pub items: Vec<EntityOrHeader>,
+    pub origins: HashMap<PathBuf, Origin>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Index of the input holding `path` and the item's directory relative to it.
+fn locate<'a>(path: &'a Path, inputs: &[PathBuf]) -> (usize, &'a Path) {
+    let (layer, relative) = inputs
+        .iter()
+        .enumerate()
+        .find_map(|(i, input)| path.strip_prefix(input).ok().map(|rel| (i, rel)))
+        .unwrap_or((0, path));
+    (layer, relative.parent().unwrap_or(Path::new("")))
+}
+
+/// Applies the inputs as layers, in order. An entity at the same path as one in an earlier
+/// input must say with `layer` whether it overrides, extends or removes it.
+pub fn apply_layers(items: Vec<EntityOrHeader>, inputs: &[PathBuf]) -> Layered {
+    let mut items: Vec<(usize, PathBuf, EntityOrHeader)> = items
+        .into_iter()
+        .map(|item| {
+            let (layer, dir) = locate(item.path(), inputs);
+            let dir = dir.to_path_buf();
+            (layer, dir, item)
+        })
+        .collect();
+    items.sort_by_key(|(layer, _, _)| *layer);
+
+    let mut slots: Vec<Option<(EntityOrHeader, Origin)>> = Vec::new();
+    let mut entities: HashMap<PathBuf, usize> = HashMap::new();
+    let mut headers: HashMap<PathBuf, usize> = HashMap::new();
+    let mut diagnostics = Vec::new();
+
+    for (layer, dir, item) in items {
+        let input = inputs.get(layer).cloned().unwrap_or_default();
+        let entity = match item {
+            EntityOrHeader::Header(header) => {
+                match headers.get(&dir) {
+                    Some(&slot) => {
+                        slots[slot] = Some((EntityOrHeader::Header(header), Origin::default()))
+                    }
+                    None => {
+                        headers.insert(dir, slots.len());
+                        slots.push(Some((EntityOrHeader::Header(header), Origin::default())));
+                    }
+                }
+                continue;
+            }
+            EntityOrHeader::Entity(entity) => entity,
+        };
+        let directive = entity.frontmatter.layer;
+
+        let Some(&slot) = entities.get(&dir) else {
+            if let Some(directive) = directive {
+                diagnostics.push(
+                    Diagnostic::error(
+                        "layer-target-missing",
+                        format!(
+                            "'{}' has `layer: {}` but no earlier input has an entity at '{}'",
+                            entity.frontmatter.name,
+                            directive_name(directive),
+                            dir.display()
+                        ),
+                    )
+                    .at(&entity.path, None),
+                );
+            }
+            if directive != Some(Layer::Remove) {
+                entities.insert(dir, slots.len());
+                let origin = Origin {
+                    layers: vec![(input, directive)],
+                    files: vec![entity.path.clone()],
+                };
+                slots.push(Some((EntityOrHeader::Entity(entity), origin)));
+            }
+            continue;
+        };
+
+        let Some((EntityOrHeader::Entity(earlier), mut origin)) = slots[slot].take() else {
+            unreachable!("entity slots hold entities");
+        };
+        let same_input = origin.layers.last().is_some_and(|(last, _)| *last == input);
+        match directive {
+            Some(directive) if !same_input => {
+                origin.layers.push((input, Some(directive)));
+                origin.files.push(entity.path.clone());
+                slots[slot] = match directive {
+                    Layer::Override => Some((EntityOrHeader::Entity(entity), origin)),
+                    Layer::Extend => {
+                        Some((EntityOrHeader::Entity(extend(earlier, entity)), origin))
+                    }
+                    Layer::Remove => {
+                        entities.remove(&dir);
+                        None
+                    }
+                };
+            }
+            _ => {
+                let message = if same_input {
+                    format!(
+                        "'{}' and '{}' define the same entity",
+                        earlier.path.display(),
+                        entity.path.display()
+                    )
+                } else {
+                    format!(
+                        "'{}' is also defined in an earlier input at '{}'; set `layer: override`, `extend` or `remove`",
+                        entity.path.display(),
+                        earlier.path.display()
+                    )
+                };
+                diagnostics
+                    .push(Diagnostic::error("layer-conflict", message).at(&entity.path, None));
+                slots[slot] = Some((EntityOrHeader::Entity(earlier), origin));
+            }
+        }
+    }
+
+    let mut layered = Layered {
+        diagnostics,
+        ..Default::default()
+    };
+    for (item, origin) in slots.into_iter().flatten() {
+        if let EntityOrHeader::Entity(entity) = &item {
+            layered.origins.insert(entity.path.clone(), origin);
+        }
+        layered.items.push(item);
+    }
+    layered
+}
+
+fn directive_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Override => "override",
+        Layer::Extend => "extend",
+        Layer::Remove => "remove",
+    }
+}
+
+/// Merges `extension` into `base`: keys it sets replace those of `base`, except `requires`,
+/// which are added. A non-empty body replaces the earlier body and file.
+fn extend(base: ParsedEntity, extension: ParsedEntity) -> ParsedEntity {
+    let Frontmatter {
+        description,
+        inline,
+        order,
+        requires,
+        skills,
+        tags,
+        deprecated,
+        when,
+        other,
+        ..
+    } = extension.frontmatter;
+    let mut frontmatter = base.frontmatter;
+    if !description.trim().is_empty() {
+        frontmatter.description = description;
+    }
+    frontmatter.inline = inline.or(frontmatter.inline);
+    frontmatter.order = order.or(frontmatter.order);
+    frontmatter.when = when.or(frontmatter.when);
+    for (list, extra) in [
+        (&mut frontmatter.requires, requires),
+        (&mut frontmatter.skills, skills),
+        (&mut frontmatter.tags, tags),
+    ] {
+        for value in extra {
+            if !list.contains(&value) {
+                list.push(value);
+            }
+        }
+    }
+    frontmatter.deprecated |= deprecated;
+    match (&mut frontmatter.other, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => base.extend(other),
+        (_, Value::Null) => {}
+        (base, other) => *base = other,
+    }
+
+    if extension.body.trim().is_empty() {
+        ParsedEntity {
+            frontmatter,
+            ..base
+        }
+    } else {
+        ParsedEntity {
+            frontmatter,
+            ..extension
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Header;
+
+    fn entity(path: &str, layer: Option<Layer>, other: &str, body: &str) -> EntityOrHeader {
+        let name = Path::new(path)
+            .parent()
+            .and_then(|p| p.file_name())
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name,
+                description: if layer == Some(Layer::Extend) {
+                    String::new()
+                } else {
+                    format!("{} description", path)
+                },
+                layer,
+                other: serde_yaml::from_str(other).unwrap(),
+                ..Default::default()
+            },
+            body: body.to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn inputs() -> Vec<PathBuf> {
+        vec![PathBuf::from("/shared"), PathBuf::from("/project")]
+    }
+
+    fn entities(layered: &Layered) -> Vec<&ParsedEntity> {
+        layered
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                EntityOrHeader::Entity(e) => Some(e),
+                EntityOrHeader::Header(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_layer_directives() {
+        let layered = apply_layers(
+            vec![
+                entity(
+                    "/project/skills/git/SKILL.md",
+                    Some(Layer::Override),
+                    "{}",
+                    "mine",
+                ),
+                entity("/shared/skills/git/SKILL.md", None, "{}", "theirs"),
+                entity(
+                    "/shared/skills/rust/SKILL.md",
+                    None,
+                    "tags: [lang]\nlevel: 1",
+                    "rust",
+                ),
+                entity(
+                    "/project/skills/rust/SKILL.md",
+                    Some(Layer::Extend),
+                    "level: 2",
+                    "",
+                ),
+                entity("/shared/skills/svn/SKILL.md", None, "{}", "svn"),
+                entity(
+                    "/project/skills/svn/SKILL.md",
+                    Some(Layer::Remove),
+                    "{}",
+                    "",
+                ),
+                EntityOrHeader::Header(Header {
+                    path: PathBuf::from("/shared/skills/HEADER.md"),
+                    ..Default::default()
+                }),
+                EntityOrHeader::Header(Header {
+                    path: PathBuf::from("/project/skills/HEADER.md"),
+                    ..Default::default()
+                }),
+            ],
+            &inputs(),
+        );
+        assert!(layered.diagnostics.is_empty());
+
+        let entities = entities(&layered);
+        assert_eq!(entities.len(), 2);
+        let git = entities
+            .iter()
+            .find(|e| e.frontmatter.name == "git")
+            .unwrap();
+        assert_eq!(git.body, "mine");
+        let rust = entities
+            .iter()
+            .find(|e| e.frontmatter.name == "rust")
+            .unwrap();
+        assert_eq!(rust.path, PathBuf::from("/shared/skills/rust/SKILL.md"));
+        assert_eq!(rust.body, "rust");
+        assert_eq!(
+            rust.frontmatter.description,
+            "/shared/skills/rust/SKILL.md description"
+        );
+        assert_eq!(
+            rust.frontmatter.other,
+            serde_yaml::from_str::<Value>("tags: [lang]\nlevel: 2").unwrap()
+        );
+
+        let headers: Vec<_> = layered
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                EntityOrHeader::Header(h) => Some(h.path.clone()),
+                EntityOrHeader::Entity(_) => None,
+            })
+            .collect();
+        assert_eq!(headers, vec![PathBuf::from("/project/skills/HEADER.md")]);
+
+        assert_eq!(
+            layered.origins[&git.path].to_string(),
+            "/shared, overridden by /project"
+        );
+        assert_eq!(
+            layered.origins[&rust.path].to_string(),
+            "/shared, extended by /project"
+        );
+    }
+
+    #[test]
+    fn test_layer_errors() {
+        let layered = apply_layers(
+            vec![
+                entity("/shared/skills/git/SKILL.md", None, "{}", "theirs"),
+                entity("/project/skills/git/SKILL.md", None, "{}", "mine"),
+                entity(
+                    "/project/skills/rust/SKILL.md",
+                    Some(Layer::Extend),
+                    "{}",
+                    "",
+                ),
+                entity("/shared/skills/svn/SKILL.md", None, "{}", "svn"),
+                entity("/shared/skills/svn/PERSONA.md", None, "{}", "svn"),
+            ],
+            &inputs(),
+        );
+        let rules: Vec<_> = layered.diagnostics.iter().map(|d| d.rule).collect();
+        assert_eq!(
+            rules,
+            vec!["layer-conflict", "layer-conflict", "layer-target-missing"]
+        );
+        // The earlier definition is kept on conflicts.
+        let git = entities(&layered)
+            .into_iter()
+            .find(|e| e.frontmatter.name == "git")
+            .unwrap();
+        assert_eq!(git.body, "theirs");
+    }
+}
@@ -0,0 +1,138 @@
+//! Entity name uniqueness across categories and inputs.
+use crate::{Diagnostic, EntityOrHeader};
+use persona_parser::ParsedEntity;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// How entities sharing a name are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateNames {
+    /// Any two entities with the same name fail validation.
+    Error,
+    /// Entities with the same name are reported as warnings.
+    #[default]
+    Warn,
+    /// Entities of different kinds may share a name, since lookups can be qualified by
+    /// kind; the same name within a kind fails validation.
+    AllowNamespaced,
+}
+
+/// Reports every entity whose name is already used by another entity, naming both files.
+pub fn check_duplicate_names(items: &[EntityOrHeader], policy: DuplicateNames) -> Vec<Diagnostic> {
+    let mut groups: BTreeMap<(&str, String), Vec<&ParsedEntity>> = BTreeMap::new();
+    for item in items {
+        if let EntityOrHeader::Entity(entity) = item {
+            let namespace = match policy {
+                DuplicateNames::AllowNamespaced => entity.kind(),
+                DuplicateNames::Error | DuplicateNames::Warn => String::new(),
+            };
+            groups
+                .entry((entity.frontmatter.name.as_str(), namespace))
+                .or_default()
+                .push(entity);
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for ((name, _), mut entities) in groups {
+        entities.sort_by(|a, b| a.path.cmp(&b.path));
+        let Some((first, rest)) = entities.split_first() else {
+            continue;
+        };
+        for entity in rest {
+            let message = format!(
+                "Entity name '{}' is used by both {} and {}",
+                name,
+                first.path.display(),
+                entity.path.display()
+            );
+            let diagnostic = match policy {
+                DuplicateNames::Warn => Diagnostic::warning("duplicate-name", message),
+                DuplicateNames::Error | DuplicateNames::AllowNamespaced => {
+                    Diagnostic::error("duplicate-name", message)
+                }
+            };
+            diagnostics.push(diagnostic.at(&entity.path, None));
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Severity;
+    use persona_parser::Frontmatter;
+    use std::path::PathBuf;
+
+    fn entity(path: &str, name: &str) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity("/in/skills/lang/rust/SKILL.md", "rust"),
+            entity("/in/personas/rust/PERSONA.md", "rust"),
+            entity("/in/skills/git/SKILL.md", "git"),
+        ]
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let errors = check_duplicate_names(&items(), DuplicateNames::Error);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].severity, Severity::Error);
+        assert_eq!(
+            errors[0].message,
+            "Entity name 'rust' is used by both /in/personas/rust/PERSONA.md and /in/skills/lang/rust/SKILL.md"
+        );
+
+        let warnings = check_duplicate_names(&items(), DuplicateNames::Warn);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+
+        assert!(check_duplicate_names(&items(), DuplicateNames::AllowNamespaced).is_empty());
+        let mut items = items();
+        items.push(entity("/in/skills/tools/rust/SKILL.md", "rust"));
+        let errors = check_duplicate_names(&items, DuplicateNames::AllowNamespaced);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].path,
+            Some(PathBuf::from("/in/skills/tools/rust/SKILL.md"))
+        );
+    }
+
+    #[test]
+    fn test_collection_reports_duplicate_names() {
+        let input = std::env::temp_dir().join("persona_test_collect_names");
+        if input.exists() {
+            std::fs::remove_dir_all(&input).unwrap();
+        }
+        for dir in ["skills/lang/rust", "skills/tools/rust"] {
+            std::fs::create_dir_all(input.join(dir)).unwrap();
+            std::fs::write(
+                input.join(dir).join("SKILL.md"),
+                "---\nname: rust\ndescription: Rust\n---\nBody",
+            )
+            .unwrap();
+        }
+
+        let inputs = vec![input.clone()];
+        let collect = |names| crate::collect_entities_with_diagnostics(&inputs, 5000, 10000, names);
+        assert!(collect(DuplicateNames::Error).has_errors());
+        let warned = collect(DuplicateNames::Warn);
+        assert!(!warned.has_errors());
+        assert_eq!(warned.diagnostics[0].rule, "duplicate-name");
+
+        std::fs::remove_dir_all(input).unwrap();
+    }
+}
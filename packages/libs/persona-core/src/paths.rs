@@ -0,0 +1,99 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Returns `path` relative to `base`, using forward slashes on every platform.
+///
+/// Both paths are made absolute against the current directory and normalised
+/// lexically (symlinks are not resolved), so the result only depends on the layout
+/// of the tree and not on where it lives on disk.
+pub fn relative_to(path: &Path, base: &Path) -> String {
+    let path = normalise(path);
+    let base = normalise(base);
+
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base.components().collect();
+
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // Different roots (e.g. drives on Windows): nothing to be relative to.
+    if common == 0 {
+        return path.to_string_lossy().replace('\\', "/");
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    for _ in common..base_components.len() {
+        parts.push("..".to_string());
+    }
+    for component in &path_components[common..] {
+        parts.push(component.as_os_str().to_string_lossy().to_string());
+    }
+
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("/")
+    }
+}
+
+fn normalise(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalised = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalised.pop();
+            }
+            other => normalised.push(other),
+        }
+    }
+    normalised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_to() {
+        assert_eq!(
+            relative_to(
+                Path::new("/repo/.agent/skills/rust/SKILL.md"),
+                Path::new("/repo")
+            ),
+            ".agent/skills/rust/SKILL.md"
+        );
+        assert_eq!(
+            relative_to(
+                Path::new("/repo/.agent/skills/SKILL.md"),
+                Path::new("/repo/out")
+            ),
+            "../.agent/skills/SKILL.md"
+        );
+        assert_eq!(
+            relative_to(Path::new("/repo/./a/../b/FILE.md"), Path::new("/repo/")),
+            "b/FILE.md"
+        );
+        assert_eq!(relative_to(Path::new("/repo"), Path::new("/repo")), ".");
+    }
+
+    #[test]
+    fn test_relative_to_relative_paths() {
+        assert_eq!(
+            relative_to(Path::new("./skills/coding/SKILL.md"), Path::new(".")),
+            "skills/coding/SKILL.md"
+        );
+        assert_eq!(
+            relative_to(Path::new("skills/SKILL.md"), Path::new("")),
+            "skills/SKILL.md"
+        );
+    }
+}
@@ -0,0 +1,567 @@
+//! Moving and renaming entities together with every reference to them.
+//!
+//! References are the structured ones only: the moved entity's own `name`, the
+//! `requires` and `skills` entries that resolve to it, and explicit paths to it relative
+//! to an input (`skills/coding/rust`) or, for entities inside a category, to the kind
+//! directory (`coding/rust`). A bare name in prose is left alone, since it may as well
+//! mean something else.
+//!
+//! A move is planned first ([`plan_move`]), which reads every entity and header and
+//! computes the updated contents without touching the tree. [`MovePlan::apply`] then
+//! stages the updated files next to the originals, renames the entity directory and
+//! swaps the staged files in. If any step fails, the files already swapped get their
+//! original contents back and the directory is moved back, leaving the tree unchanged.
+use crate::bundle::resolve_skills;
+use crate::names::DuplicateNames;
+use crate::requires::resolve_requirements;
+use crate::resolve::{find_entity, input_for};
+use crate::{EntityOrHeader, PersonaError};
+use persona_parser::is_valid_name;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const STAGING_SUFFIX: &str = ".persona-mv";
+
+/// A file whose references to the moved entity are rewritten.
+#[derive(Debug)]
+pub struct FileEdit {
+    /// Where the file is now.
+    pub source: PathBuf,
+    /// Where the file is after the move.
+    pub path: PathBuf,
+    pub original: String,
+    pub updated: String,
+    pub replacements: usize,
+}
+
+#[derive(Debug)]
+pub struct MovePlan {
+    pub from_dir: PathBuf,
+    pub to_dir: PathBuf,
+    pub old_name: String,
+    pub new_name: String,
+    pub edits: Vec<FileEdit>,
+}
+
+/// Plans moving the entity `query` refers to (see [`find_entity`]) to `destination`,
+/// given as `category/.../name` relative to the entity's kind directory.
+///
+/// A new name that another entity already uses is rejected, unless `names` allows
+/// entities of different kinds to share it.
+pub fn plan_move(
+    items: &[EntityOrHeader],
+    inputs: &[PathBuf],
+    query: &str,
+    destination: &str,
+    names: DuplicateNames,
+) -> Result<MovePlan, PersonaError> {
+    let entity = find_entity(items, inputs, query)?;
+    let from_dir = entity
+        .path
+        .parent()
+        .ok_or_else(|| persona_parser::PersonaError::ParentDirNotFound(query.to_string()))?
+        .to_path_buf();
+    let input = input_for(&from_dir, inputs)
+        .ok_or_else(|| PersonaError::EntityNotFound(query.to_string()))?;
+    let from_rel = from_dir
+        .strip_prefix(input)
+        .map_err(|_| PersonaError::EntityNotFound(query.to_string()))?;
+    let kind_dir = from_rel
+        .components()
+        .next()
+        .map(|c| PathBuf::from(c.as_os_str()))
+        .unwrap_or_default();
+
+    let mut to_kind_rel = PathBuf::new();
+    for component in Path::new(destination).components() {
+        match component {
+            Component::Normal(part) => to_kind_rel.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(PersonaError::InvalidPath(format!(
+                    "destination '{}' must be relative to the kind directory",
+                    destination
+                )));
+            }
+        }
+    }
+    let new_name = to_kind_rel
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !is_valid_name(&new_name) {
+        return Err(persona_parser::PersonaError::InvalidNameFormat(new_name).into());
+    }
+
+    let to_rel = kind_dir.join(&to_kind_rel);
+    let to_dir = input.join(&to_rel);
+    if to_dir.exists() {
+        return Err(PersonaError::AlreadyExists(to_dir.display().to_string()));
+    }
+    if to_dir.starts_with(&from_dir) {
+        return Err(PersonaError::InvalidPath(format!(
+            "cannot move '{}' into itself",
+            from_dir.display()
+        )));
+    }
+
+    let old_name = entity.frontmatter.name.clone();
+    if new_name != old_name {
+        let taken = items.iter().find_map(|item| match item {
+            EntityOrHeader::Entity(e)
+                if e.frontmatter.name == new_name
+                    && (names != DuplicateNames::AllowNamespaced || e.kind() == entity.kind()) =>
+            {
+                Some(e)
+            }
+            _ => None,
+        });
+        if let Some(other) = taken {
+            return Err(PersonaError::NameTaken {
+                name: new_name,
+                path: other.path.display().to_string(),
+            });
+        }
+    }
+
+    let from_kind_rel = from_rel.strip_prefix(&kind_dir).unwrap_or(from_rel);
+    let input_path = Replacement::new(&slashed(from_rel), &slashed(&to_rel), false);
+    let kind_path = Replacement::new(&slashed(from_kind_rel), &slashed(&to_kind_rel), true);
+    // Without a category the kind-relative path is the bare name
+    let paths: Vec<&Replacement> = if from_kind_rel.components().count() > 1 {
+        vec![&input_path, &kind_path]
+    } else {
+        vec![&input_path]
+    };
+    let moved_query = |query: &str| {
+        let query = query.trim_end_matches('/');
+        if query == old_name {
+            return new_name.clone();
+        }
+        [&input_path, &kind_path]
+            .iter()
+            .fold(query.to_string(), |query, r| r.apply(&query).0)
+    };
+
+    let mut edits = Vec::new();
+    for item in items {
+        let source = item.path();
+        let original = fs::read_to_string(source)?;
+
+        let mut fields: Vec<(&str, Vec<Replacement>)> = Vec::new();
+        if let EntityOrHeader::Entity(e) = item {
+            if e.path == entity.path {
+                fields.push(("name", vec![Replacement::new(&old_name, &new_name, true)]));
+            }
+            let requires = resolve_requirements(e, items, inputs)
+                .into_iter()
+                .filter(|(_, resolved)| resolved.as_ref().is_ok_and(|r| r.path == entity.path))
+                .map(|(query, _)| Replacement::new(query, &moved_query(query), true))
+                .collect();
+            fields.push(("requires", requires));
+            let skills = e
+                .frontmatter
+                .skills
+                .iter()
+                .zip(resolve_skills(e, items, inputs))
+                .filter(|(_, resolved)| resolved.as_ref().is_ok_and(|r| r.path == entity.path))
+                .map(|(query, _)| Replacement::new(query, &moved_query(query), true))
+                .collect();
+            fields.push(("skills", skills));
+        }
+
+        let (mut updated, mut count) = rewrite_fields(&original, &fields);
+        for replacement in &paths {
+            if replacement.old != replacement.new {
+                let (text, n) = replacement.apply(&updated);
+                updated = text;
+                count += n;
+            }
+        }
+        if count > 0 {
+            let path = match source.strip_prefix(&from_dir) {
+                Ok(rest) => to_dir.join(rest),
+                Err(_) => source.to_path_buf(),
+            };
+            edits.push(FileEdit {
+                source: source.to_path_buf(),
+                path,
+                original,
+                updated,
+                replacements: count,
+            });
+        }
+    }
+
+    Ok(MovePlan {
+        from_dir,
+        to_dir,
+        old_name,
+        new_name,
+        edits,
+    })
+}
+
+impl MovePlan {
+    /// Moves the entity directory and writes the updated files.
+    pub fn apply(&self) -> Result<(), PersonaError> {
+        let mut moved = false;
+        let mut swapped = 0;
+        let result = self.try_apply(&mut moved, &mut swapped);
+        if result.is_err() {
+            self.roll_back(moved, swapped);
+        }
+        result?;
+
+        // Drop category directories the move left empty; the kind directory always
+        // still holds the moved entity, so this never climbs out of the input.
+        remove_empty_parents(&self.from_dir, &self.to_dir);
+        Ok(())
+    }
+
+    /// Runs the steps of [`MovePlan::apply`], recording how far it got.
+    fn try_apply(&self, moved: &mut bool, swapped: &mut usize) -> std::io::Result<()> {
+        for edit in &self.edits {
+            fs::write(staging(&edit.source), &edit.updated)?;
+        }
+
+        if let Some(parent) = self.to_dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&self.from_dir, &self.to_dir)?;
+        *moved = true;
+
+        for edit in &self.edits {
+            fs::rename(staging(&edit.path), &edit.path)?;
+            *swapped += 1;
+        }
+        Ok(())
+    }
+
+    /// Undoes the first `swapped` swaps, the directory move and the staged files.
+    /// Errors are ignored, since the error that caused the roll back is reported.
+    fn roll_back(&self, moved: bool, swapped: usize) {
+        for edit in &self.edits[..swapped] {
+            let _ = fs::write(&edit.path, &edit.original);
+        }
+        for edit in &self.edits[swapped..] {
+            let _ = fs::remove_file(staging(if moved { &edit.path } else { &edit.source }));
+        }
+        if moved && fs::rename(&self.to_dir, &self.from_dir).is_ok() {
+            remove_empty_parents(&self.to_dir, &self.from_dir);
+        }
+    }
+}
+
+/// Removes the directories above `dir` that are empty, up to the first one that is
+/// not empty or also holds `keep`.
+fn remove_empty_parents(dir: &Path, keep: &Path) {
+    let mut dir = dir.parent();
+    while let Some(parent) = dir {
+        if keep.starts_with(parent) || fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+}
+
+fn staging(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(STAGING_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn slashed(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Applies the replacements listed for a frontmatter key to that key's value only, whether
+/// written inline (`requires: [a, b]`) or as an indented block.
+fn rewrite_fields(text: &str, fields: &[(&str, Vec<Replacement>)]) -> (String, usize) {
+    let mut out = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut lines = text.split_inclusive('\n');
+    let Some(first) = lines.next() else {
+        return (out, count);
+    };
+    out.push_str(first);
+    if first.trim_end() != "---" {
+        out.extend(lines);
+        return (out, count);
+    }
+
+    let mut current: Option<&[Replacement]> = None;
+    for line in lines.by_ref() {
+        if line.trim_end() == "---" {
+            out.push_str(line);
+            break;
+        }
+        let continued = line.starts_with([' ', '\t', '-']);
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) if !continued => {
+                current = fields
+                    .iter()
+                    .find(|(field, _)| *field == key.trim())
+                    .map(|(_, replacements)| replacements.as_slice());
+                (&line[..=key.len()], value)
+            }
+            _ if continued => ("", line),
+            _ => {
+                current = None;
+                (line, "")
+            }
+        };
+        let mut value = value.to_string();
+        for replacement in current.unwrap_or_default() {
+            if replacement.old != replacement.new {
+                let (text, n) = replacement.apply(&value);
+                value = text;
+                count += n;
+            }
+        }
+        out.push_str(key);
+        out.push_str(&value);
+    }
+    out.extend(lines);
+    (out, count)
+}
+
+/// Replaces whole-token occurrences of `old`, where tokens are runs of name characters.
+struct Replacement {
+    old: String,
+    new: String,
+    /// Also refuse matches directly after a `/`, so a relative path is not matched
+    /// inside a longer path.
+    anchored: bool,
+}
+
+impl Replacement {
+    fn new(old: &str, new: &str, anchored: bool) -> Self {
+        Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            anchored,
+        }
+    }
+
+    fn apply(&self, text: &str) -> (String, usize) {
+        let is_token = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        let mut out = String::with_capacity(text.len());
+        let mut count = 0;
+        let mut rest = text;
+        while let Some(idx) = rest.find(&self.old) {
+            let before = rest[..idx].chars().last().or_else(|| out.chars().last());
+            let after = rest[idx + self.old.len()..].chars().next();
+            let bounded = !before.is_some_and(|c| is_token(c) || (self.anchored && c == '/'))
+                && !after.is_some_and(is_token);
+            out.push_str(&rest[..idx]);
+            if bounded {
+                out.push_str(&self.new);
+                count += 1;
+            } else {
+                out.push_str(&self.old);
+            }
+            rest = &rest[idx + self.old.len()..];
+        }
+        out.push_str(rest);
+        (out, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect_entities;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_replacement_whole_tokens() {
+        let r = Replacement::new("rust", "rust-lang", false);
+        assert_eq!(
+            r.apply("rust, rusty, rust-review, ../rust/SKILL.md, `rust`"),
+            (
+                "rust-lang, rusty, rust-review, ../rust-lang/SKILL.md, `rust-lang`".to_string(),
+                3
+            )
+        );
+
+        let anchored = Replacement::new("coding/rust", "tools/rust", true);
+        assert_eq!(
+            anchored.apply("see coding/rust and other/coding/rust").0,
+            "see tools/rust and other/coding/rust"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_fields() {
+        let text = "---\nname: go\ndescription: go and go/x\nrequires:\n  - go\n  - gopher\nskills: [go, coding/go]\n---\nrequires: go\n";
+        let fields = [
+            ("requires", vec![Replacement::new("go", "golang", true)]),
+            (
+                "skills",
+                vec![Replacement::new("coding/go", "lang/go", true)],
+            ),
+        ];
+        assert_eq!(
+            rewrite_fields(text, &fields),
+            (
+                "---\nname: go\ndescription: go and go/x\nrequires:\n  - golang\n  - gopher\nskills: [go, lang/go]\n---\nrequires: go\n".to_string(),
+                2
+            )
+        );
+    }
+
+    #[test]
+    fn test_move_entity() {
+        let input = std::env::temp_dir().join("persona_test_rename");
+        if input.exists() {
+            fs::remove_dir_all(&input).unwrap();
+        }
+        write(
+            &input.join("skills/coding/rust-review/SKILL.md"),
+            "---\nname: rust-review\ndescription: Review Rust\n---\nUse rust-review.\n",
+        );
+        write(
+            &input.join("skills/coding/rust-review/checklist.txt"),
+            "asset",
+        );
+        // A persona of the same name, which is not renamed with the skill.
+        write(
+            &input.join("personas/rust-review/PERSONA.md"),
+            "---\nname: rust-review\ndescription: Reviewer\nskills: [coding/rust-review]\n---\nRuns the rust-review skill.\n",
+        );
+        write(
+            &input.join("personas/dev/PERSONA.md"),
+            "---\nname: dev\ndescription: Dev\nrequires: [personas/rust-review]\nskills:\n  - rust-review\n---\nSee skills/coding/rust-review/SKILL.md and coding/rust-review.\n",
+        );
+
+        let inputs = vec![input.clone()];
+        let items = collect_entities(&inputs, 5000, 10000).unwrap();
+        let plan = plan_move(
+            &items,
+            &inputs,
+            "skills/coding/rust-review",
+            "review/rust-audit",
+            DuplicateNames::Warn,
+        )
+        .unwrap();
+
+        assert_eq!(plan.to_dir, input.join("skills/review/rust-audit"));
+        assert_eq!(plan.edits.len(), 3);
+        // Planning does not touch the tree.
+        assert!(input.join("skills/coding/rust-review").exists());
+
+        plan.apply().unwrap();
+
+        assert!(!input.join("skills/coding").exists());
+        assert!(
+            input
+                .join("skills/review/rust-audit/checklist.txt")
+                .exists()
+        );
+        assert_eq!(
+            fs::read_to_string(input.join("skills/review/rust-audit/SKILL.md")).unwrap(),
+            "---\nname: rust-audit\ndescription: Review Rust\n---\nUse rust-review.\n"
+        );
+        assert_eq!(
+            fs::read_to_string(input.join("personas/rust-review/PERSONA.md")).unwrap(),
+            "---\nname: rust-review\ndescription: Reviewer\nskills: [review/rust-audit]\n---\nRuns the rust-review skill.\n"
+        );
+        assert_eq!(
+            fs::read_to_string(input.join("personas/dev/PERSONA.md")).unwrap(),
+            "---\nname: dev\ndescription: Dev\nrequires: [personas/rust-review]\nskills:\n  - rust-audit\n---\nSee skills/review/rust-audit/SKILL.md and review/rust-audit.\n"
+        );
+        let items = collect_entities(&inputs, 5000, 10000).unwrap();
+        assert!(find_entity(&items, &inputs, "rust-audit").is_ok());
+
+        assert!(matches!(
+            plan_move(
+                &items,
+                &inputs,
+                "rust-audit",
+                "../escape",
+                DuplicateNames::Warn
+            ),
+            Err(PersonaError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            plan_move(&items, &inputs, "rust-audit", "Bad", DuplicateNames::Warn),
+            Err(PersonaError::Parse(_))
+        ));
+        // The skill now holds the name; only a persona may share it when names are
+        // namespaced by kind.
+        assert!(matches!(
+            plan_move(&items, &inputs, "dev", "rust-audit", DuplicateNames::Warn),
+            Err(PersonaError::NameTaken { .. })
+        ));
+        assert!(
+            plan_move(
+                &items,
+                &inputs,
+                "dev",
+                "rust-audit",
+                DuplicateNames::AllowNamespaced
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            plan_move(
+                &items,
+                &inputs,
+                "personas/rust-review",
+                "team/dev",
+                DuplicateNames::AllowNamespaced
+            ),
+            Err(PersonaError::NameTaken { .. })
+        ));
+
+        fs::remove_dir_all(input).unwrap();
+    }
+
+    #[test]
+    fn test_failed_move_rolls_back() {
+        let input = std::env::temp_dir().join("persona_test_rename_rollback");
+        if input.exists() {
+            fs::remove_dir_all(&input).unwrap();
+        }
+        let skill = "---\nname: go\ndescription: Go\n---\nUse go.\n";
+        let persona = "---\nname: dev\ndescription: Dev\nskills: [go]\n---\nUses go.\n";
+        write(&input.join("skills/coding/go/SKILL.md"), skill);
+        write(&input.join("personas/dev/PERSONA.md"), persona);
+
+        let inputs = vec![input.clone()];
+        let items = collect_entities(&inputs, 5000, 10000).unwrap();
+        let mut plan =
+            plan_move(&items, &inputs, "go", "lang/golang", DuplicateNames::Warn).unwrap();
+        assert_eq!(plan.edits.len(), 2);
+        // Swapping a file over a directory fails after the other files are swapped.
+        let notes = input.join("personas/dev/notes");
+        write(&notes.join("todo.txt"), "asset");
+        plan.edits.push(FileEdit {
+            source: notes.clone(),
+            path: notes.clone(),
+            original: String::new(),
+            updated: String::new(),
+            replacements: 1,
+        });
+
+        assert!(plan.apply().is_err());
+        assert_eq!(
+            fs::read_to_string(input.join("skills/coding/go/SKILL.md")).unwrap(),
+            skill
+        );
+        assert_eq!(
+            fs::read_to_string(input.join("personas/dev/PERSONA.md")).unwrap(),
+            persona
+        );
+        assert!(!input.join("skills/lang").exists());
+        assert!(!staging(&notes).exists());
+        assert!(!staging(&input.join("personas/dev/PERSONA.md")).exists());
+
+        fs::remove_dir_all(input).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+//! Dependencies between entities declared with the `requires` frontmatter field.
+//!
+//! A requirement names another entity the same way `persona show` does (see
+//! [`find_entity`]). Requirements must resolve to exactly one entity, must not form a
+//! cycle and must not point at a `deprecated` entity. The `skills` a persona lists (see
+//! [`crate::bundle`]) must each resolve to exactly one skill.
+use crate::bundle::{PERSONA_KIND, resolve_skills};
+use crate::resolve::find_entity;
+use crate::{Diagnostic, EntityOrHeader, PersonaError};
+use persona_parser::ParsedEntity;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Resolves each of `entity`'s requirements, in the order they are listed.
+pub fn resolve_requirements<'a>(
+    entity: &'a ParsedEntity,
+    items: &'a [EntityOrHeader],
+    inputs: &[PathBuf],
+) -> Vec<(&'a str, Result<&'a ParsedEntity, PersonaError>)> {
+    entity
+        .frontmatter
+        .requires
+        .iter()
+        .map(|query| (query.as_str(), find_entity(items, inputs, query)))
+        .collect()
+}
+
+/// Checks the requirements of every entity in `items`.
+pub fn check_requirements(items: &[EntityOrHeader], inputs: &[PathBuf]) -> Vec<Diagnostic> {
+    let mut entities: Vec<&ParsedEntity> = items
+        .iter()
+        .filter_map(|item| match item {
+            EntityOrHeader::Entity(e) => Some(e),
+            EntityOrHeader::Header(_) => None,
+        })
+        .collect();
+    entities.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut diagnostics = Vec::new();
+    let mut edges: HashMap<&Path, Vec<&ParsedEntity>> = HashMap::new();
+    for entity in &entities {
+        let name = &entity.frontmatter.name;
+        for (query, resolved) in resolve_requirements(entity, items, inputs) {
+            match resolved {
+                Ok(required) if required.frontmatter.deprecated => {
+                    diagnostics.push(
+                        Diagnostic::error(
+                            "deprecated-requirement",
+                            format!(
+                                "'{}' requires deprecated entity '{}'",
+                                name, required.frontmatter.name
+                            ),
+                        )
+                        .at(&entity.path, None),
+                    );
+                    edges.entry(&entity.path).or_default().push(required);
+                }
+                Ok(required) => edges.entry(&entity.path).or_default().push(required),
+                Err(e) => diagnostics.push(
+                    Diagnostic::error(
+                        "unknown-requirement",
+                        format!("'{}' requires '{}': {}", name, query, e),
+                    )
+                    .at(&entity.path, None),
+                ),
+            }
+        }
+        if entity.kind() == PERSONA_KIND {
+            for skill in resolve_skills(entity, items, inputs) {
+                if let Err(e) = skill {
+                    diagnostics.push(
+                        Diagnostic::error("unknown-skill", format!("'{}' skills: {}", name, e))
+                            .at(&entity.path, None),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut state = HashMap::new();
+    for entity in &entities {
+        let mut stack = Vec::new();
+        find_cycles(entity, &edges, &mut state, &mut stack, &mut diagnostics);
+    }
+    diagnostics
+}
+
+/// Checks that what the entities in `kept` require, and the skills kept personas list,
+/// are kept as well. `kept` is the subset of `items` one build writes, after `when`
+/// conditions, tags, filters and bundling; requirements are resolved in `items`.
+pub fn check_kept_requirements(
+    kept: &[EntityOrHeader],
+    items: &[EntityOrHeader],
+    inputs: &[PathBuf],
+) -> Vec<Diagnostic> {
+    let kept_paths: HashSet<&Path> = kept.iter().map(|item| item.path()).collect();
+    let mut diagnostics = Vec::new();
+    for item in kept {
+        let EntityOrHeader::Entity(entity) = item else {
+            continue;
+        };
+        let name = &entity.frontmatter.name;
+        let requires = resolve_requirements(entity, items, inputs)
+            .into_iter()
+            .filter_map(|(_, required)| required.ok())
+            .map(|required| ("requires", required));
+        let skills = resolve_skills(entity, items, inputs)
+            .into_iter()
+            .filter(|_| entity.kind() == PERSONA_KIND)
+            .filter_map(Result::ok)
+            .map(|skill| ("lists skill", skill));
+        for (relation, required) in requires.chain(skills) {
+            if !kept_paths.contains(required.path.as_path()) {
+                diagnostics.push(
+                    Diagnostic::error(
+                        "excluded-requirement",
+                        format!(
+                            "'{}' {} '{}', which this build leaves out",
+                            name, relation, required.frontmatter.name
+                        ),
+                    )
+                    .at(&entity.path, None),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+/// Depth-first search reporting every requirement that leads back into the current path.
+fn find_cycles<'a>(
+    entity: &'a ParsedEntity,
+    edges: &HashMap<&Path, Vec<&'a ParsedEntity>>,
+    state: &mut HashMap<&'a Path, Visit>,
+    stack: &mut Vec<&'a ParsedEntity>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if state.contains_key(entity.path.as_path()) {
+        return;
+    }
+    state.insert(&entity.path, Visit::InProgress);
+    stack.push(entity);
+    for required in edges.get(entity.path.as_path()).into_iter().flatten() {
+        match state.get(required.path.as_path()) {
+            Some(Visit::InProgress) => {
+                let start = stack
+                    .iter()
+                    .position(|e| e.path == required.path)
+                    .unwrap_or(0);
+                let mut names: Vec<&str> = stack[start..]
+                    .iter()
+                    .map(|e| e.frontmatter.name.as_str())
+                    .collect();
+                names.push(&required.frontmatter.name);
+                diagnostics.push(
+                    Diagnostic::error(
+                        "requirement-cycle",
+                        format!("Requirement cycle: {}", names.join(" -> ")),
+                    )
+                    .at(&stack[start].path, None),
+                );
+            }
+            Some(Visit::Done) => {}
+            None => find_cycles(required, edges, state, stack, diagnostics),
+        }
+    }
+    stack.pop();
+    state.insert(&entity.path, Visit::Done);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(path: &str, name: &str, requires: &[&str], deprecated: bool) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                requires: requires.iter().map(|r| r.to_string()).collect(),
+                deprecated,
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn messages(items: &[EntityOrHeader]) -> Vec<(String, &'static str)> {
+        check_requirements(items, &[PathBuf::from("/in")])
+            .into_iter()
+            .map(|d| (d.message, d.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_requirements() {
+        let items = vec![
+            entity(
+                "/in/personas/dev/PERSONA.md",
+                "dev",
+                &["rust", "skills/vcs/git"],
+                false,
+            ),
+            entity("/in/skills/lang/rust/SKILL.md", "rust", &["git"], false),
+            entity("/in/skills/vcs/git/SKILL.md", "git", &[], false),
+            entity("/in/skills/vcs/svn/SKILL.md", "svn", &[], true),
+        ];
+        assert!(messages(&items).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_requirements() {
+        let mut items = vec![
+            entity(
+                "/in/personas/dev/PERSONA.md",
+                "dev",
+                &["python", "svn"],
+                false,
+            ),
+            entity("/in/skills/a/SKILL.md", "a", &["b"], false),
+            entity("/in/skills/b/SKILL.md", "b", &["c"], false),
+            entity("/in/skills/c/SKILL.md", "c", &["a"], false),
+            entity("/in/skills/self/SKILL.md", "self", &["self"], false),
+            entity("/in/skills/svn/SKILL.md", "svn", &[], true),
+        ];
+        if let EntityOrHeader::Entity(dev) = &mut items[0] {
+            dev.frontmatter.skills = vec!["svn".to_string(), "dev".to_string()];
+        }
+        assert_eq!(
+            messages(&items),
+            vec![
+                (
+                    "'dev' requires 'python': No entity matches 'python'".to_string(),
+                    "unknown-requirement"
+                ),
+                (
+                    "'dev' requires deprecated entity 'svn'".to_string(),
+                    "deprecated-requirement"
+                ),
+                (
+                    "'dev' skills: No entity matches 'dev'".to_string(),
+                    "unknown-skill"
+                ),
+                (
+                    "Requirement cycle: a -> b -> c -> a".to_string(),
+                    "requirement-cycle"
+                ),
+                (
+                    "Requirement cycle: self -> self".to_string(),
+                    "requirement-cycle"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kept_requirements() {
+        let mut items = vec![
+            entity("/in/personas/dev/PERSONA.md", "dev", &[], false),
+            entity("/in/skills/a/SKILL.md", "a", &["b"], false),
+            entity("/in/skills/b/SKILL.md", "b", &[], false),
+            entity("/in/skills/c/SKILL.md", "c", &[], false),
+        ];
+        if let EntityOrHeader::Entity(dev) = &mut items[0] {
+            dev.frontmatter.skills = vec!["c".to_string()];
+        }
+        let inputs = [PathBuf::from("/in")];
+        assert!(check_kept_requirements(&items, &items, &inputs).is_empty());
+
+        let kept = vec![items[0].clone(), items[1].clone()];
+        let messages: Vec<_> = check_kept_requirements(&kept, &items, &inputs)
+            .into_iter()
+            .map(|d| (d.message, d.rule))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    "'dev' lists skill 'c', which this build leaves out".to_string(),
+                    "excluded-requirement"
+                ),
+                (
+                    "'a' requires 'b', which this build leaves out".to_string(),
+                    "excluded-requirement"
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{EntityOrHeader, PersonaError};
+use persona_parser::ParsedEntity;
+use std::path::{Path, PathBuf};
+
+/// Returns the input directory `path` lives in.
+pub fn input_for<'a>(path: &Path, inputs: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    inputs.iter().find(|input| path.starts_with(input))
+}
+
+/// Finds the entity `query` refers to. A query is an entity name, the path of its
+/// directory or definition file relative to an input (`skills/coding/rust`), or the path
+/// relative to the kind directory (`coding/rust`). Fails when nothing or more than one
+/// entity matches.
+pub fn find_entity<'a>(
+    items: &'a [EntityOrHeader],
+    inputs: &[PathBuf],
+    query: &str,
+) -> Result<&'a ParsedEntity, PersonaError> {
+    find_entity_of_kind(items, inputs, query, None)
+}
+
+/// Like [`find_entity`], but only considers entities of `kind` when given.
+pub fn find_entity_of_kind<'a>(
+    items: &'a [EntityOrHeader],
+    inputs: &[PathBuf],
+    query: &str,
+    kind: Option<&str>,
+) -> Result<&'a ParsedEntity, PersonaError> {
+    let query = query.trim_end_matches('/');
+    let matches: Vec<&ParsedEntity> = items
+        .iter()
+        .filter_map(|item| match item {
+            EntityOrHeader::Entity(e) => Some(e),
+            EntityOrHeader::Header(_) => None,
+        })
+        .filter(|e| kind.is_none_or(|kind| e.kind() == kind))
+        .filter(|e| matches_query(e, inputs, query))
+        .collect();
+
+    match matches.as_slice() {
+        [entity] => Ok(entity),
+        [] => Err(PersonaError::EntityNotFound(query.to_string())),
+        _ => Err(PersonaError::AmbiguousEntity {
+            query: query.to_string(),
+            matches: matches
+                .iter()
+                .map(|e| e.path.display().to_string())
+                .collect(),
+        }),
+    }
+}
+
+fn matches_query(entity: &ParsedEntity, inputs: &[PathBuf], query: &str) -> bool {
+    if entity.frontmatter.name == query || entity.path == Path::new(query) {
+        return true;
+    }
+    let Some(dir) = entity.path.parent() else {
+        return false;
+    };
+    if dir == Path::new(query) {
+        return true;
+    }
+    let Some(relative) = input_for(dir, inputs).and_then(|input| dir.strip_prefix(input).ok())
+    else {
+        return false;
+    };
+    // A query naming the definition file refers to its directory.
+    let query = Path::new(query);
+    let query = match query.parent() {
+        Some(parent) if query.file_name() == entity.path.file_name() => parent,
+        _ => query,
+    };
+    relative == query || relative.components().skip(1).eq(query.components())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+
+    fn entity(path: &str, name: &str) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        })
+    }
+
+    #[test]
+    fn test_find_entity() {
+        let inputs = vec![PathBuf::from("/in")];
+        let items = vec![
+            entity("/in/skills/coding/rust/SKILL.md", "rust"),
+            entity("/in/skills/review/go/SKILL.md", "go"),
+            entity("/in/personas/go/PERSONA.md", "go"),
+        ];
+
+        for query in [
+            "rust",
+            "skills/coding/rust",
+            "skills/coding/rust/",
+            "skills/coding/rust/SKILL.md",
+            "coding/rust",
+            "/in/skills/coding/rust/SKILL.md",
+        ] {
+            let found = find_entity(&items, &inputs, query).unwrap();
+            assert_eq!(found.frontmatter.name, "rust", "{query}");
+        }
+
+        assert!(matches!(
+            find_entity(&items, &inputs, "go"),
+            Err(PersonaError::AmbiguousEntity { .. })
+        ));
+        assert_eq!(
+            find_entity(&items, &inputs, "personas/go").unwrap().path,
+            PathBuf::from("/in/personas/go/PERSONA.md")
+        );
+        assert_eq!(
+            find_entity_of_kind(&items, &inputs, "go", Some("persona"))
+                .unwrap()
+                .path,
+            PathBuf::from("/in/personas/go/PERSONA.md")
+        );
+        assert!(matches!(
+            find_entity(&items, &inputs, "python"),
+            Err(PersonaError::EntityNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+//! Scaffolding of new entities from templates.
+//!
+//! A new entity of kind `skill` at `coding/rust-review` is created as
+//! `<input>/skills/coding/rust-review/SKILL.md`. Its content comes from
+//! `<input>/.templates/skill.md` when present, or from a built-in default. Templates use
+//! Jinja syntax and receive `name`, `kind`, `category` (the parent directories, joined
+//! with `/`) and `title` (the name with hyphens replaced by spaces).
+use crate::PersonaError;
+use crate::template::template_error;
+use minijinja::{Environment, context};
+use persona_parser::is_valid_name;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Directory inside an input that holds the per-kind entity templates.
+pub const TEMPLATES_DIR: &str = ".templates";
+
+const DEFAULT_TEMPLATE: &str = "---
+name: {{ name }}
+description: TODO describe when to use this {{ kind }}
+---
+# {{ title }}
+
+TODO
+";
+
+/// Creates a new entity of `kind` at `entity_path` (`category/.../name`) inside `input`
+/// and returns the path of the definition file. Existing files are never overwritten.
+pub fn new_entity(input: &Path, kind: &str, entity_path: &str) -> Result<PathBuf, PersonaError> {
+    if !is_valid_name(kind) {
+        return Err(persona_parser::PersonaError::InvalidNameFormat(kind.to_string()).into());
+    }
+
+    let mut components = Vec::new();
+    for component in Path::new(entity_path).components() {
+        match component {
+            Component::Normal(part) => components.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => {
+                return Err(PersonaError::InvalidPath(format!(
+                    "entity path '{}' must be relative to the kind directory",
+                    entity_path
+                )));
+            }
+        }
+    }
+    let Some(name) = components.pop() else {
+        return Err(persona_parser::PersonaError::InvalidNameFormat(String::new()).into());
+    };
+    if !is_valid_name(&name) {
+        return Err(persona_parser::PersonaError::InvalidNameFormat(name).into());
+    }
+
+    let dir = components
+        .iter()
+        .fold(input.join(format!("{}s", kind)), |dir, part| dir.join(part))
+        .join(&name);
+    let file = dir.join(format!("{}.md", kind.to_uppercase()));
+    if file.exists() {
+        return Err(PersonaError::AlreadyExists(file.display().to_string()));
+    }
+
+    let template_path = input.join(TEMPLATES_DIR).join(format!("{}.md", kind));
+    let (template_name, source) = if template_path.exists() {
+        (
+            template_path.to_string_lossy().to_string(),
+            fs::read_to_string(&template_path)?,
+        )
+    } else {
+        ("<default>".to_string(), DEFAULT_TEMPLATE.to_string())
+    };
+
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    env.add_template(&template_name, &source)
+        .map_err(|e| template_error(&template_name, e))?;
+    let content = env
+        .get_template(&template_name)
+        .and_then(|t| {
+            t.render(context! {
+                name => name,
+                kind => kind,
+                category => components.join("/"),
+                title => name.replace('-', " "),
+            })
+        })
+        .map_err(|e| template_error(&template_name, e))?;
+
+    fs::create_dir_all(&dir)?;
+    let mut handle = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&file)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                PersonaError::AlreadyExists(file.display().to_string())
+            }
+            _ => PersonaError::Io(e),
+        })?;
+    handle.write_all(content.as_bytes())?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::{MarkdownParser, PersonaParser as _};
+
+    fn temp_input(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("persona_test_scaffold_{}", name));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_new_entity_default_template() {
+        let input = temp_input("default");
+
+        let file = new_entity(&input, "skill", "coding/rust-review").unwrap();
+
+        assert_eq!(file, input.join("skills/coding/rust-review/SKILL.md"));
+        let entity = MarkdownParser.parse(&file).unwrap();
+        assert_eq!(entity.frontmatter.name, "rust-review");
+        assert!(entity.body.contains("# rust review"));
+
+        // Never overwrites.
+        assert!(matches!(
+            new_entity(&input, "skill", "coding/rust-review"),
+            Err(PersonaError::AlreadyExists(_))
+        ));
+
+        fs::remove_dir_all(input).unwrap();
+    }
+
+    #[test]
+    fn test_new_entity_custom_template() {
+        let input = temp_input("custom");
+        fs::create_dir_all(input.join(TEMPLATES_DIR)).unwrap();
+        fs::write(
+            input.join(TEMPLATES_DIR).join("persona.md"),
+            "---\nname: {{ name }}\ndescription: A {{ category }} persona\n---\nYou are {{ title }}.\n",
+        )
+        .unwrap();
+
+        let file = new_entity(&input, "persona", "engineering/tech-lead").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file).unwrap(),
+            "---\nname: tech-lead\ndescription: A engineering persona\n---\nYou are tech lead.\n"
+        );
+
+        fs::remove_dir_all(input).unwrap();
+    }
+
+    #[test]
+    fn test_new_entity_invalid_names() {
+        let input = temp_input("invalid");
+
+        for path in ["coding/Rust", "coding/rust_review", "../escape", ""] {
+            assert!(new_entity(&input, "skill", path).is_err(), "{path}");
+        }
+        assert!(new_entity(&input, "Skill", "rust").is_err());
+        assert!(matches!(
+            new_entity(&input, "skill", "/abs/rust"),
+            Err(PersonaError::InvalidPath(_))
+        ));
+        assert!(!input.join("skills").exists());
+
+        fs::remove_dir_all(input).unwrap();
+    }
+}
@@ -0,0 +1,524 @@
+//! Full-text search over entity names, descriptions and bodies.
+//!
+//! The index is an inverted index ranked with BM25, where a term in the name counts
+//! three times and a term in the description twice as much as one in the body. Queries
+//! are whitespace separated terms, optionally mixed with field filters such as
+//! `kind:skill`, `name:rust`, `category:coding` or `path:review`. It can be stored on
+//! disk and reused as long as none of the files the indexed entities were read from
+//! changed.
+use crate::layers::Origin;
+use crate::resolve::input_for;
+use crate::{EntityOrHeader, PersonaError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+const NAME_WEIGHT: usize = 3;
+const DESCRIPTION_WEIGHT: usize = 2;
+const SNIPPET_CHARS: usize = 160;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document {
+    name: String,
+    kind: String,
+    path: PathBuf,
+    category: String,
+    description: String,
+    body: String,
+    /// Weighted number of terms.
+    length: usize,
+}
+
+/// Version of the cached index layout; bump it whenever [`SearchIndex`] or the way
+/// documents are indexed changes, so older caches are rebuilt.
+const INDEX_FORMAT: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchIndex {
+    /// Identifies the index format, the inputs, and the indexed files with their
+    /// modification times.
+    fingerprint: String,
+    documents: Vec<Document>,
+    /// Term -> (document, weighted term frequency).
+    postings: HashMap<String, Vec<(usize, usize)>>,
+}
+
+/// A field filter in a search query.
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Kind(String),
+    Name(String),
+    Category(String),
+    Path(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SearchQuery {
+    terms: Vec<String>,
+    filters: Vec<Filter>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Result<Self, PersonaError> {
+        let mut parsed = Self::default();
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some((field, value)) if !value.is_empty() => {
+                    let value = value.to_lowercase();
+                    parsed.filters.push(match field {
+                        "kind" => Filter::Kind(value),
+                        "name" => Filter::Name(value),
+                        "category" => Filter::Category(value),
+                        "path" => Filter::Path(value),
+                        _ => {
+                            return Err(PersonaError::InvalidQuery(format!(
+                                "unknown field '{}' (expected kind, name, category or path)",
+                                field
+                            )));
+                        }
+                    });
+                }
+                _ => parsed
+                    .terms
+                    .extend(tokenize(word).into_iter().map(|(t, _)| t)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Highlighted excerpt of a matching document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub text: String,
+    /// Byte ranges in `text` of the matched terms.
+    pub highlights: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub name: String,
+    pub kind: String,
+    pub path: PathBuf,
+    pub score: f64,
+    pub snippet: Snippet,
+}
+
+impl SearchIndex {
+    /// Indexes the entities in `items`. `origins` holds the files each entity was
+    /// layered from (see [`crate::Collection::origins`]), which the stored index is
+    /// checked against.
+    pub fn build(
+        items: &[EntityOrHeader],
+        origins: &HashMap<PathBuf, Origin>,
+        inputs: &[PathBuf],
+    ) -> Self {
+        let mut documents = Vec::new();
+        let mut postings: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+
+        for item in items {
+            let EntityOrHeader::Entity(entity) = item else {
+                continue;
+            };
+            let id = documents.len();
+            let mut frequencies: BTreeMap<String, usize> = BTreeMap::new();
+            for (text, weight) in [
+                (entity.frontmatter.name.as_str(), NAME_WEIGHT),
+                (entity.frontmatter.description.as_str(), DESCRIPTION_WEIGHT),
+                (entity.body.as_str(), 1),
+            ] {
+                for (term, _) in tokenize(text) {
+                    *frequencies.entry(term).or_default() += weight;
+                }
+            }
+            let length = frequencies.values().sum();
+            for (term, frequency) in frequencies {
+                postings.entry(term).or_default().push((id, frequency));
+            }
+
+            let dir = entity.path.parent().unwrap_or(Path::new(""));
+            let category = input_for(dir, inputs)
+                .and_then(|input| dir.strip_prefix(input).ok())
+                .and_then(Path::parent)
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default();
+            documents.push(Document {
+                name: entity.frontmatter.name.clone(),
+                kind: entity.kind(),
+                path: entity.path.clone(),
+                category,
+                description: entity.frontmatter.description.clone(),
+                body: entity.body.clone(),
+                length,
+            });
+        }
+
+        Self {
+            fingerprint: fingerprint(items, origins, inputs),
+            documents,
+            postings,
+        }
+    }
+
+    /// Loads the index stored at `cache` if it is still valid for `items`, otherwise
+    /// builds it and stores it there.
+    pub fn cached(
+        items: &[EntityOrHeader],
+        origins: &HashMap<PathBuf, Origin>,
+        inputs: &[PathBuf],
+        cache: &Path,
+    ) -> Result<Self, PersonaError> {
+        let current = fingerprint(items, origins, inputs);
+        if let Ok(content) = std::fs::read_to_string(cache) {
+            match serde_json::from_str::<Self>(&content) {
+                Ok(index) if index.fingerprint == current => return Ok(index),
+                Ok(_) => tracing::debug!("Search index cache is stale, rebuilding"),
+                Err(e) => tracing::warn!("Ignoring unreadable search index cache: {}", e),
+            }
+        }
+
+        let index = Self::build(items, origins, inputs);
+        if let Some(parent) = cache.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(&index)
+            .map_err(|e| PersonaError::Serialization(e.to_string()))?;
+        std::fs::write(cache, content)?;
+        Ok(index)
+    }
+
+    /// Returns up to `limit` documents matching `query`, best first. A query without
+    /// terms lists every document passing the filters, by name.
+    pub fn search(&self, query: &SearchQuery, limit: usize) -> Vec<SearchHit> {
+        let candidates: Vec<bool> = self
+            .documents
+            .iter()
+            .map(|doc| self.passes_filters(doc, &query.filters))
+            .collect();
+
+        let mut scored: Vec<(usize, f64)> = if query.terms.is_empty() {
+            (0..self.documents.len())
+                .filter(|&id| candidates[id])
+                .map(|id| (id, 0.0))
+                .collect()
+        } else {
+            let mut scores: HashMap<usize, f64> = HashMap::new();
+            let total = self.documents.len() as f64;
+            let average =
+                self.documents.iter().map(|d| d.length).sum::<usize>() as f64 / total.max(1.0);
+            for term in &query.terms {
+                let Some(postings) = self.postings.get(term) else {
+                    continue;
+                };
+                let df = postings.len() as f64;
+                let idf = ((total - df + 0.5) / (df + 0.5) + 1.0).ln();
+                for &(id, frequency) in postings {
+                    if !candidates[id] {
+                        continue;
+                    }
+                    let tf = frequency as f64;
+                    let length = self.documents[id].length as f64;
+                    let norm = K1 * (1.0 - B + B * length / average.max(1.0));
+                    *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+                }
+            }
+            scores.into_iter().collect()
+        };
+
+        scored.sort_by(|(a_id, a), (b_id, b)| {
+            b.total_cmp(a)
+                .then_with(|| self.documents[*a_id].name.cmp(&self.documents[*b_id].name))
+        });
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(id, score)| {
+                let doc = &self.documents[id];
+                SearchHit {
+                    name: doc.name.clone(),
+                    kind: doc.kind.clone(),
+                    path: doc.path.clone(),
+                    score,
+                    snippet: snippet(doc, &query.terms),
+                }
+            })
+            .collect()
+    }
+
+    fn passes_filters(&self, doc: &Document, filters: &[Filter]) -> bool {
+        filters.iter().all(|filter| match filter {
+            Filter::Kind(kind) => doc.kind == *kind,
+            Filter::Name(name) => doc.name.contains(name.as_str()),
+            Filter::Category(category) => doc.category.to_lowercase().contains(category.as_str()),
+            Filter::Path(path) => doc
+                .path
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(path.as_str()),
+        })
+    }
+}
+
+/// Lowercased alphanumeric terms with their byte ranges in `text`.
+fn tokenize(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                terms.push((text[s..i].to_lowercase(), s..i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    terms
+}
+
+/// Picks the body line with the most matched terms (the description when nothing in
+/// the body matches) and trims it to a window around the first match.
+fn snippet(doc: &Document, terms: &[String]) -> Snippet {
+    let matches = |line: &str| -> Vec<Range<usize>> {
+        tokenize(line)
+            .into_iter()
+            .filter(|(term, _)| terms.contains(term))
+            .map(|(_, range)| range)
+            .collect()
+    };
+
+    let best = doc
+        .body
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| (line, matches(line)))
+        .filter(|(_, ranges)| !ranges.is_empty())
+        .max_by_key(|(_, ranges)| ranges.len());
+    let (line, ranges) = best.unwrap_or_else(|| {
+        let description = doc.description.trim();
+        (description, matches(description))
+    });
+
+    let first = ranges.first().map_or(0, |r| r.start);
+    let mut start = first.saturating_sub(SNIPPET_CHARS / 4);
+    while !line.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (start + SNIPPET_CHARS).min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let prefix = if start > 0 { "..." } else { "" };
+    let suffix = if end < line.len() { "..." } else { "" };
+    let offset = prefix.len();
+    Snippet {
+        text: format!("{}{}{}", prefix, &line[start..end], suffix),
+        highlights: ranges
+            .into_iter()
+            .filter(|r| r.start >= start && r.end <= end)
+            .map(|r| r.start - start + offset..r.end - start + offset)
+            .collect(),
+    }
+}
+
+/// The index format, the inputs in order (categories and paths are relative to them),
+/// and for every indexed entity the paths, sizes and modification times of all the files
+/// it was layered from, so that changing an earlier or later layer invalidates it too.
+fn fingerprint(
+    items: &[EntityOrHeader],
+    origins: &HashMap<PathBuf, Origin>,
+    inputs: &[PathBuf],
+) -> String {
+    let mut fingerprint = format!("format:{}\n", INDEX_FORMAT);
+    for input in inputs {
+        fingerprint.push_str(&format!("input:{}\n", input.display()));
+    }
+    for item in items {
+        let EntityOrHeader::Entity(entity) = item else {
+            continue;
+        };
+        fingerprint.push_str(&format!("entity:{}\n", entity.path.display()));
+        let files = origins
+            .get(&entity.path)
+            .map_or(std::slice::from_ref(&entity.path), |o| &o.files);
+        for path in files {
+            let (len, modified) = std::fs::metadata(path)
+                .map(|m| {
+                    let modified = m
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map_or(0, |d| d.as_nanos());
+                    (m.len(), modified)
+                })
+                .unwrap_or_default();
+            fingerprint.push_str(&format!("{}:{}:{}\n", path.display(), len, modified));
+        }
+    }
+    fingerprint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::{Frontmatter, ParsedEntity};
+
+    fn entity(path: &str, name: &str, description: &str, body: &str) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(path),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: description.to_string(),
+                ..Default::default()
+            },
+            body: body.to_string(),
+            char_count: 0,
+        })
+    }
+
+    fn inputs() -> Vec<PathBuf> {
+        vec![PathBuf::from("/in")]
+    }
+
+    fn items() -> Vec<EntityOrHeader> {
+        vec![
+            entity(
+                "/in/skills/db/migrations/SKILL.md",
+                "migrations",
+                "Write database migrations",
+                "Always make database migrations reversible.\nTest them on a copy.",
+            ),
+            entity(
+                "/in/skills/db/sql/SKILL.md",
+                "sql",
+                "Write SQL queries",
+                "Prefer explicit joins. A database index helps.",
+            ),
+            entity(
+                "/in/personas/dba/PERSONA.md",
+                "dba",
+                "Database administrator",
+                "You look after the database and its migrations.",
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_search_ranks_by_bm25() {
+        let index = SearchIndex::build(&items(), &HashMap::new(), &inputs());
+        let query = SearchQuery::parse("database migrations").unwrap();
+
+        let hits = index.search(&query, 10);
+
+        let names: Vec<_> = hits.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["migrations", "dba", "sql"]);
+        assert!(hits[0].score > hits[1].score);
+
+        let snippet = &hits[0].snippet;
+        assert_eq!(snippet.text, "Always make database migrations reversible.");
+        let highlighted: Vec<_> = snippet
+            .highlights
+            .iter()
+            .map(|r| &snippet.text[r.clone()])
+            .collect();
+        assert_eq!(highlighted, vec!["database", "migrations"]);
+    }
+
+    #[test]
+    fn test_search_filters() {
+        let index = SearchIndex::build(&items(), &HashMap::new(), &inputs());
+
+        let query = SearchQuery::parse("kind:persona database").unwrap();
+        let hits = index.search(&query, 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "dba");
+
+        let query = SearchQuery::parse("category:db").unwrap();
+        let names: Vec<_> = index
+            .search(&query, 10)
+            .into_iter()
+            .map(|h| h.name)
+            .collect();
+        assert_eq!(names, vec!["migrations", "sql"]);
+
+        assert!(matches!(
+            SearchQuery::parse("owner:me"),
+            Err(PersonaError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_index_cache() {
+        let dir = std::env::temp_dir().join("persona_test_search_cache");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        let cache = dir.join("index.json");
+
+        let built = SearchIndex::cached(&items(), &HashMap::new(), &inputs(), &cache).unwrap();
+        assert!(cache.exists());
+        let loaded = SearchIndex::cached(&items(), &HashMap::new(), &inputs(), &cache).unwrap();
+        assert_eq!(loaded.fingerprint, built.fingerprint);
+        assert_eq!(loaded.documents.len(), 3);
+
+        // The same files seen through other inputs are indexed anew.
+        let other_inputs = vec![PathBuf::from("/other")];
+        let rebuilt =
+            SearchIndex::cached(&items(), &HashMap::new(), &other_inputs, &cache).unwrap();
+        assert_ne!(rebuilt.fingerprint, built.fingerprint);
+        assert!(rebuilt.fingerprint.starts_with("format:1\n"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_index_cache_tracks_layers() {
+        let dir = std::env::temp_dir().join("persona_test_search_layers");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        let write = |path: &Path, content: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        let base = dir.join("base");
+        let project = dir.join("project");
+        write(
+            &base.join("skills/git/SKILL.md"),
+            "---\nname: git\ndescription: Use git\n---\nCommit often.\n",
+        );
+        // Without a body, the entity keeps pointing to the base file.
+        let extension = project.join("skills/git/SKILL.md");
+        write(
+            &extension,
+            "---\nname: git\nlayer: extend\ndescription: Rebase\n---\n",
+        );
+        let inputs = vec![base, project];
+        let cache = dir.join("index.json");
+        let fingerprint = || {
+            let collection =
+                crate::collect_entities_with_diagnostics(&inputs, 5000, 10000, Default::default());
+            SearchIndex::cached(&collection.items, &collection.origins, &inputs, &cache)
+                .unwrap()
+                .fingerprint
+        };
+
+        let built = fingerprint();
+        assert!(built.contains(&extension.display().to_string()));
+        write(
+            &extension,
+            "---\nname: git\nlayer: extend\ndescription: Rebase onto main\n---\n",
+        );
+        assert_ne!(fingerprint(), built);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+//! Entity tags from the `tags` frontmatter field, and selecting entities by tag.
+use crate::{EntityOrHeader, retain_entities};
+use persona_parser::ParsedEntity;
+
+/// Selects entities carrying any of `include` (or every entity when it is empty) and
+/// none of `exclude`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TagFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, entity: &ParsedEntity) -> bool {
+        let has = |tag: &String| entity.frontmatter.tags.contains(tag);
+        (self.include.is_empty() || self.include.iter().any(has)) && !self.exclude.iter().any(has)
+    }
+
+    /// Keeps the matching entities and the headers of categories that still contain one.
+    pub fn apply(&self, items: Vec<EntityOrHeader>) -> Vec<EntityOrHeader> {
+        if self.is_empty() {
+            return items;
+        }
+        retain_entities(items, |e| self.matches(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use persona_parser::Frontmatter;
+    use std::path::PathBuf;
+
+    fn entity(name: &str, tags: &[&str]) -> ParsedEntity {
+        ParsedEntity {
+            path: PathBuf::from(format!("/in/skills/{name}/SKILL.md")),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: "d".to_string(),
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            },
+            body: "b".to_string(),
+            char_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let api = entity("api", &["backend", "shared"]);
+        let css = entity("css", &["frontend"]);
+        let git = entity("git", &[]);
+
+        let backend = TagFilter::new(vec!["backend".to_string()], vec![]);
+        assert!(backend.matches(&api));
+        assert!(!backend.matches(&css));
+        assert!(!backend.matches(&git));
+
+        let not_frontend = TagFilter::new(vec![], vec!["frontend".to_string()]);
+        assert!(not_frontend.matches(&api));
+        assert!(!not_frontend.matches(&css));
+        assert!(not_frontend.matches(&git));
+
+        let shared_only = TagFilter::new(vec!["shared".to_string()], vec!["backend".to_string()]);
+        assert!(!shared_only.matches(&api));
+    }
+}
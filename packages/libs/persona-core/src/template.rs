@@ -0,0 +1,284 @@
+//! Template-driven rendering of the agents file.
+//!
+//! Templates use Jinja syntax (via `minijinja`) and receive the following context:
+//!
+//! - `root_header`: contents of the root `HEADER.md`, if any.
+//! - `categories`: the top-level nodes of the category tree. Each node has `name`,
+//!   `path`, `header`, `entity` (set when the directory defines an entity) and `children`.
+//! - `entities`: a flat list of every entity, in tree order.
+//! - `headers`: a flat list of every category header, in tree order.
+//!
+//! Entities expose `name`, `description`, `kind`, `path`, `category`, `body` and
+//! `frontmatter` (every frontmatter field, including `name` and `description`).
+use crate::paths::relative_to;
+use crate::tree::NodeRef;
+use crate::{EntityOrHeader, PersonaError};
+use minijinja::Environment;
+use persona_parser::ParsedEntity;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Clone)]
+struct TemplateEntity<'a> {
+    name: &'a str,
+    description: &'a str,
+    kind: String,
+    path: String,
+    category: Vec<String>,
+    body: &'a str,
+    frontmatter: serde_yaml::Mapping,
+}
+
+#[derive(Serialize, Clone)]
+struct TemplateHeader<'a> {
+    path: String,
+    category: Vec<String>,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct TemplateNode<'a> {
+    name: String,
+    path: String,
+    header: Option<&'a str>,
+    entity: Option<TemplateEntity<'a>>,
+    children: Vec<TemplateNode<'a>>,
+}
+
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    root_header: Option<&'a str>,
+    categories: Vec<TemplateNode<'a>>,
+    entities: Vec<TemplateEntity<'a>>,
+    headers: Vec<TemplateHeader<'a>>,
+}
+
+/// Renders the collected items through a user supplied template.
+///
+/// `name` is used in error messages, so it should identify the template file. Paths in
+/// the context are relative to `base_dir`, the directory the output is written to.
+pub fn render_template(
+    items: &[EntityOrHeader],
+    inputs: &[PathBuf],
+    root_header: Option<&str>,
+    base_dir: &Path,
+    name: &str,
+    source: &str,
+) -> Result<String, PersonaError> {
+    let root = NodeRef::build(items, inputs)?;
+
+    let mut entities = Vec::new();
+    let mut headers = Vec::new();
+    let categories = build_nodes(&root, &[], base_dir, &mut entities, &mut headers);
+
+    let context = TemplateContext {
+        root_header,
+        categories,
+        entities,
+        headers,
+    };
+
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.add_template(name, source)
+        .map_err(|e| template_error(name, e))?;
+    let template = env
+        .get_template(name)
+        .map_err(|e| template_error(name, e))?;
+    template
+        .render(context)
+        .map_err(|e| template_error(name, e))
+}
+
+fn build_nodes<'a>(
+    node: &NodeRef<'a>,
+    category: &[String],
+    base_dir: &Path,
+    entities: &mut Vec<TemplateEntity<'a>>,
+    headers: &mut Vec<TemplateHeader<'a>>,
+) -> Vec<TemplateNode<'a>> {
+    let mut nodes = Vec::new();
+    for (name, child) in node.ordered_children() {
+        let mut child_category = category.to_vec();
+        child_category.push(name.clone());
+
+        if let Some(header) = child.header {
+            headers.push(TemplateHeader {
+                path: relative_to(&header.path, base_dir),
+                category: child_category.clone(),
+                body: header.body.trim(),
+            });
+        }
+
+        let entity = child.entity.map(|e| template_entity(e, category, base_dir));
+        if let Some(entity) = &entity {
+            entities.push(entity.clone());
+        }
+
+        let children = build_nodes(child, &child_category, base_dir, entities, headers);
+        nodes.push(TemplateNode {
+            name: name.clone(),
+            path: child_category.join("/"),
+            header: child.header.map(|h| h.body.trim()),
+            entity,
+            children,
+        });
+    }
+    nodes
+}
+
+fn template_entity<'a>(
+    entity: &'a ParsedEntity,
+    category: &[String],
+    base_dir: &Path,
+) -> TemplateEntity<'a> {
+    let mut frontmatter = serde_yaml::Mapping::new();
+    frontmatter.insert("name".into(), entity.frontmatter.name.clone().into());
+    frontmatter.insert(
+        "description".into(),
+        entity.frontmatter.description.clone().into(),
+    );
+    if let Some(inline) = entity.frontmatter.inline {
+        frontmatter.insert("inline".into(), inline.into());
+    }
+    for (key, list) in [
+        ("requires", &entity.frontmatter.requires),
+        ("skills", &entity.frontmatter.skills),
+        ("tags", &entity.frontmatter.tags),
+    ] {
+        if !list.is_empty() {
+            frontmatter.insert(key.into(), list.clone().into());
+        }
+    }
+    if entity.frontmatter.deprecated {
+        frontmatter.insert("deprecated".into(), true.into());
+    }
+    if let Some(other) = entity.frontmatter.other.as_mapping() {
+        for (k, v) in other {
+            frontmatter.insert(k.clone(), v.clone());
+        }
+    }
+
+    TemplateEntity {
+        name: &entity.frontmatter.name,
+        description: &entity.frontmatter.description,
+        kind: entity.kind(),
+        path: relative_to(&entity.path, base_dir),
+        category: category.to_vec(),
+        body: &entity.body,
+        frontmatter,
+    }
+}
+
+pub(crate) fn template_error(name: &str, err: minijinja::Error) -> PersonaError {
+    let message = match err.detail() {
+        Some(detail) => format!("{}: {}", err.kind(), detail),
+        None => err.kind().to_string(),
+    };
+    PersonaError::Template {
+        name: err.name().unwrap_or(name).to_string(),
+        line: err.line(),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Header;
+    use persona_parser::Frontmatter;
+    use serde_yaml::Mapping;
+
+    fn items() -> Vec<EntityOrHeader> {
+        let mut other = Mapping::new();
+        other.insert("license".into(), "MIT".into());
+
+        vec![
+            EntityOrHeader::Header(Header {
+                path: PathBuf::from("./skills/coding/HEADER.md"),
+                body: "Coding skills\n".to_string(),
+                ..Default::default()
+            }),
+            EntityOrHeader::Entity(ParsedEntity {
+                path: PathBuf::from("./skills/coding/rust/SKILL.md"),
+                frontmatter: Frontmatter {
+                    name: "rust".to_string(),
+                    description: "Rust Skill".to_string(),
+                    other: serde_yaml::Value::Mapping(other),
+                    ..Default::default()
+                },
+                body: "Use cargo.".to_string(),
+                char_count: 0,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_render_template_flat_entities() {
+        let inputs = vec![PathBuf::from(".")];
+        let source = "{% for e in entities %}\n- {{ e.name }} ({{ e.kind }}): {{ e.description }} [{{ e.frontmatter.license }}] {{ e.path }}\n{% endfor %}";
+
+        let output =
+            render_template(&items(), &inputs, None, Path::new("."), "flat.j2", source).unwrap();
+
+        assert_eq!(
+            output,
+            "- rust (skill): Rust Skill [MIT] skills/coding/rust/SKILL.md\n"
+        );
+    }
+
+    #[test]
+    fn test_render_template_category_tree() {
+        let inputs = vec![PathBuf::from(".")];
+        let source = r#"{{ root_header }}
+{% macro walk(nodes) %}
+{% for node in nodes %}
+{% if node.entity %}
+* {{ node.entity.name }} in {{ node.entity.category | join("/") }}
+{% else %}
+# {{ node.path }}{% if node.header %}: {{ node.header }}{% endif %}
+
+{{ walk(node.children) }}
+{%- endif %}
+{% endfor %}
+{% endmacro %}
+{{ walk(categories) }}"#;
+
+        let output = render_template(
+            &items(),
+            &inputs,
+            Some("Root"),
+            Path::new("."),
+            "tree.j2",
+            source,
+        )
+        .unwrap();
+
+        assert!(output.starts_with("Root\n"));
+        assert!(output.contains("# skills\n"));
+        assert!(output.contains("# skills/coding: Coding skills\n"));
+        assert!(output.contains("* rust in skills/coding\n"));
+    }
+
+    #[test]
+    fn test_render_template_reports_line_numbers() {
+        let inputs = vec![PathBuf::from(".")];
+        let source = "line one\n{% for e in entities %}\n{{ e.name }\n{% endfor %}";
+
+        let err = render_template(&items(), &inputs, None, Path::new("."), "broken.j2", source)
+            .unwrap_err();
+
+        match &err {
+            PersonaError::Template { name, line, .. } => {
+                assert_eq!(name, "broken.j2");
+                assert_eq!(*line, Some(3));
+            }
+            other => panic!("Expected template error, got {:?}", other),
+        }
+        assert!(
+            err.to_string()
+                .starts_with("Template error in broken.j2 at line 3:")
+        );
+    }
+}
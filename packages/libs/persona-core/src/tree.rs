@@ -0,0 +1,118 @@
+use crate::{EntityOrHeader, Header, PersonaError};
+use persona_parser::ParsedEntity;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Explicit position of a tree node among its siblings, from the frontmatter of the
+/// entity or category header in its directory. The entity's `order` wins when both set one.
+pub(crate) fn node_order(entity: Option<&ParsedEntity>, header: Option<&Header>) -> Option<i64> {
+    entity
+        .and_then(|e| e.frontmatter.order)
+        .or_else(|| header.and_then(|h| h.order))
+}
+
+/// Category tree built from the collected items, keyed by directory name
+/// relative to the input root the item was found in.
+pub(crate) struct NodeRef<'a> {
+    pub(crate) children: BTreeMap<String, NodeRef<'a>>,
+    pub(crate) entity: Option<&'a ParsedEntity>,
+    pub(crate) header: Option<&'a Header>,
+}
+
+impl<'a> NodeRef<'a> {
+    fn new() -> Self {
+        Self {
+            children: BTreeMap::new(),
+            entity: None,
+            header: None,
+        }
+    }
+
+    fn order(&self) -> Option<i64> {
+        node_order(self.entity, self.header)
+    }
+
+    /// Children in output order: explicitly ordered nodes first (ascending), then the
+    /// rest. Ties keep the alphabetical order of the map.
+    pub(crate) fn ordered_children(&self) -> Vec<(&String, &NodeRef<'a>)> {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by_key(|(_, node)| (node.order().is_none(), node.order()));
+        children
+    }
+
+    /// Appends the paths of every header and entity below this node in output order.
+    pub(crate) fn collect_paths(&self, paths: &mut Vec<&'a Path>) {
+        for (_, child) in self.ordered_children() {
+            if let Some(header) = child.header {
+                paths.push(&header.path);
+            }
+            if let Some(entity) = child.entity {
+                paths.push(&entity.path);
+            }
+            child.collect_paths(paths);
+        }
+    }
+
+    pub(crate) fn build(
+        items: &'a [EntityOrHeader],
+        inputs: &[PathBuf],
+    ) -> Result<Self, PersonaError> {
+        let mut root = NodeRef::new();
+        for item in items {
+            let path = item.path();
+
+            let mut relative_path = None;
+            for input in inputs {
+                if let Ok(rel) = path.strip_prefix(input) {
+                    relative_path = Some(rel);
+                    break;
+                }
+            }
+
+            let rel_path = match relative_path {
+                Some(p) => p,
+                None => path,
+            };
+
+            let parent = rel_path.parent().ok_or_else(|| {
+                PersonaError::Serialization(format!("Item has no parent directory: {:?}", path))
+            })?;
+
+            let components: Vec<String> = parent
+                .iter()
+                .map(|c| c.to_string_lossy().to_string())
+                .filter(|s| s != ".")
+                .collect();
+
+            let mut current_node = &mut root;
+            for component in components {
+                current_node = current_node
+                    .children
+                    .entry(component)
+                    .or_insert_with(NodeRef::new);
+            }
+
+            match item {
+                EntityOrHeader::Entity(e) => {
+                    if current_node.entity.is_some() {
+                        return Err(PersonaError::Serialization(format!(
+                            "Duplicate entity at path {:?}",
+                            path
+                        )));
+                    }
+                    current_node.entity = Some(e);
+                }
+                EntityOrHeader::Header(h) => {
+                    if current_node.header.is_some() {
+                        return Err(PersonaError::Serialization(format!(
+                            "Duplicate header at path {:?}",
+                            path
+                        )));
+                    }
+                    current_node.header = Some(h);
+                }
+            }
+        }
+        Ok(root)
+    }
+}
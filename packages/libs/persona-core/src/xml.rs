@@ -1,72 +1,72 @@
-use crate::{EntityOrHeader, Header, PersonaError};
+pub mod reader;
+
+use crate::paths::relative_to;
+use crate::requires::resolve_requirements;
+use crate::tree::NodeRef;
+use crate::{EntityOrHeader, PersonaError};
 use persona_parser::ParsedEntity;
 use quick_xml::Writer;
-use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
-use std::collections::BTreeMap;
+use quick_xml::events::{BytesCData, BytesEnd, BytesStart, BytesText, Event};
+use std::collections::HashMap;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// How text content (descriptions, frontmatter values, directions) is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EscapeMode {
+    /// Escape XML special characters (`<`, `>`, `&`, `'`, `"`).
+    #[default]
+    Escape,
+    /// Wrap text in CDATA sections.
+    Cdata,
+    /// Write descriptions and frontmatter values verbatim. Kept for compatibility with
+    /// files generated by older versions; produces malformed XML for special characters.
+    Raw,
+}
+
+/// Options controlling how `AGENTS.md` is generated.
+#[derive(Debug, Clone, Default)]
+pub struct XmlOptions {
+    pub escape: EscapeMode,
+    /// Inline bodies of entities at or below this many tokens as a `<content>` element.
+    /// Entities can opt in or out with the `inline` frontmatter flag.
+    pub inline_max_tokens: Option<u64>,
+    /// Directory the generated file is written to. `path` attributes are emitted
+    /// relative to it. Defaults to the current directory.
+    pub base_dir: Option<PathBuf>,
+}
+
+impl XmlOptions {
+    fn base_dir(&self) -> &Path {
+        self.base_dir.as_deref().unwrap_or(Path::new("."))
+    }
+
+    fn should_inline(&self, entity: &ParsedEntity) -> bool {
+        match entity.frontmatter.inline {
+            Some(inline) => inline,
+            None => self
+                .inline_max_tokens
+                .is_some_and(|max| entity.body_tokens() as u64 <= max),
+        }
+    }
+}
 
 pub fn generate_xml(
     items: &[EntityOrHeader],
     inputs: &[PathBuf],
     root_header: Option<&str>,
 ) -> Result<String, PersonaError> {
-    let mut root = NodeRef::new();
-    for item in items {
-        let path = item.path();
-
-        let mut relative_path = None;
-        for input in inputs {
-            if let Ok(rel) = path.strip_prefix(input) {
-                relative_path = Some(rel);
-                break;
-            }
-        }
-
-        let rel_path = match relative_path {
-            Some(p) => p,
-            None => path,
-        };
-
-        let parent = rel_path.parent().ok_or_else(|| {
-            PersonaError::Serialization(format!("Item has no parent directory: {:?}", path))
-        })?;
-
-        let components: Vec<String> = parent
-            .iter()
-            .map(|c| c.to_string_lossy().to_string())
-            .filter(|s| s != ".")
-            .collect();
-
-        let mut current_node = &mut root;
-        for component in components {
-            current_node = current_node
-                .children
-                .entry(component)
-                .or_insert_with(NodeRef::new);
-        }
+    generate_xml_with_options(items, inputs, root_header, &XmlOptions::default())
+}
 
-        match item {
-            EntityOrHeader::Entity(e) => {
-                if current_node.entity.is_some() {
-                    return Err(PersonaError::Serialization(format!(
-                        "Duplicate entity at path {:?}",
-                        path
-                    )));
-                }
-                current_node.entity = Some(e);
-            }
-            EntityOrHeader::Header(h) => {
-                if current_node.header.is_some() {
-                    return Err(PersonaError::Serialization(format!(
-                        "Duplicate header at path {:?}",
-                        path
-                    )));
-                }
-                current_node.header = Some(h);
-            }
-        }
-    }
+pub fn generate_xml_with_options(
+    items: &[EntityOrHeader],
+    inputs: &[PathBuf],
+    root_header: Option<&str>,
+    options: &XmlOptions,
+) -> Result<String, PersonaError> {
+    // 1. Build the category tree
+    let root = NodeRef::build(items, inputs)?;
 
     // 2. Generate XML
     let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
@@ -75,14 +75,33 @@ pub fn generate_xml(
     writer.write_event(Event::Start(BytesStart::new("persona-context")))?;
 
     if let Some(header_content) = root_header {
-        let desc_elem = BytesStart::new("directions");
-        writer.write_event(Event::Start(desc_elem))?;
-        writer.write_event(Event::Text(BytesText::new(header_content.trim())))?;
-        writer.write_event(Event::End(BytesEnd::new("directions")))?;
+        write_directions(&mut writer, header_content, options.escape)?;
     }
 
+    // Requirements are written with the name and path of the entity they resolve to
+    let requires: HashMap<&Path, Vec<(String, Option<String>)>> = items
+        .iter()
+        .filter_map(|item| match item {
+            EntityOrHeader::Entity(e) if !e.frontmatter.requires.is_empty() => Some(e),
+            _ => None,
+        })
+        .map(|entity| {
+            let resolved = resolve_requirements(entity, items, inputs)
+                .into_iter()
+                .map(|(query, required)| match required {
+                    Ok(required) => (
+                        required.frontmatter.name.clone(),
+                        Some(relative_to(&required.path, options.base_dir())),
+                    ),
+                    Err(_) => (query.to_string(), None),
+                })
+                .collect();
+            (entity.path.as_path(), resolved)
+        })
+        .collect();
+
     // Recurse
-    write_node(&mut writer, &root)?;
+    write_node(&mut writer, &root, options, &requires)?;
 
     writer.write_event(Event::End(BytesEnd::new("persona-context")))?;
 
@@ -91,92 +110,213 @@ pub fn generate_xml(
     Ok(result)
 }
 
-struct NodeRef<'a> {
-    children: BTreeMap<String, NodeRef<'a>>,
-    entity: Option<&'a ParsedEntity>,
-    header: Option<&'a Header>,
-}
-
-impl<'a> NodeRef<'a> {
-    fn new() -> Self {
-        Self {
-            children: BTreeMap::new(),
-            entity: None,
-            header: None,
-        }
-    }
-}
+/// Resolved requirements of each entity: name and relative path of the required entity.
+type Requirements<'a> = HashMap<&'a Path, Vec<(String, Option<String>)>>;
 
-fn write_node<W: Write>(writer: &mut Writer<W>, node: &NodeRef) -> Result<(), PersonaError> {
-    for (name, child_node) in &node.children {
-        let mut elem = BytesStart::new(name);
+fn write_node<W: Write>(
+    writer: &mut Writer<W>,
+    node: &NodeRef,
+    options: &XmlOptions,
+    requires: &Requirements,
+) -> Result<(), PersonaError> {
+    for (name, child_node) in node.ordered_children() {
+        let fallback = if child_node.entity.is_some() {
+            "entity"
+        } else {
+            "category"
+        };
+        let mut elem = start_element(name, fallback);
+        let end = elem.to_end().into_owned();
 
         if let Some(entity) = child_node.entity {
             // Attribute: path
-            elem.push_attribute(("path", entity.path.to_string_lossy().as_ref()));
+            elem.push_attribute((
+                "path",
+                relative_to(&entity.path, options.base_dir()).as_str(),
+            ));
         }
 
         writer.write_event(Event::Start(elem))?;
 
         if let Some(header) = child_node.header {
-            let desc_elem = BytesStart::new("directions");
-            writer.write_event(Event::Start(desc_elem.clone()))?;
-            writer.write_event(Event::Text(BytesText::new(header.body.trim())))?;
-            writer.write_event(Event::End(BytesEnd::new("directions")))?;
+            write_directions(writer, &header.body, options.escape)?;
         }
 
         if let Some(entity) = child_node.entity {
             // Description
             let desc_elem = BytesStart::new("description");
             writer.write_event(Event::Start(desc_elem.clone()))?;
-            writer.write_event(Event::Text(BytesText::from_escaped(
-                &entity.frontmatter.description,
-            )))?;
+            write_text(writer, &entity.frontmatter.description, options.escape)?;
             writer.write_event(Event::End(BytesEnd::new("description")))?;
 
+            if let Some(required) = requires.get(entity.path.as_path()) {
+                writer.write_event(Event::Start(BytesStart::new("requires")))?;
+                for (name, path) in required {
+                    let mut item = BytesStart::new("item");
+                    if let Some(path) = path {
+                        item.push_attribute(("path", path.as_str()));
+                    }
+                    writer.write_event(Event::Start(item))?;
+                    write_text(writer, name, options.escape)?;
+                    writer.write_event(Event::End(BytesEnd::new("item")))?;
+                }
+                writer.write_event(Event::End(BytesEnd::new("requires")))?;
+            }
+
+            for (tag, list) in [
+                ("skills", &entity.frontmatter.skills),
+                ("tags", &entity.frontmatter.tags),
+            ] {
+                if list.is_empty() {
+                    continue;
+                }
+                writer.write_event(Event::Start(BytesStart::new(tag)))?;
+                for value in list {
+                    writer.write_event(Event::Start(BytesStart::new("item")))?;
+                    write_text(writer, value, options.escape)?;
+                    writer.write_event(Event::End(BytesEnd::new("item")))?;
+                }
+                writer.write_event(Event::End(BytesEnd::new(tag)))?;
+            }
+
+            if entity.frontmatter.deprecated {
+                writer.write_event(Event::Start(BytesStart::new("deprecated")))?;
+                write_text(writer, "true", options.escape)?;
+                writer.write_event(Event::End(BytesEnd::new("deprecated")))?;
+            }
+
             // Other frontmatter fields
-            write_yaml_value(writer, &entity.frontmatter.other)?;
+            write_yaml_value(writer, &entity.frontmatter.other, options.escape)?;
+
+            if options.should_inline(entity) {
+                writer.write_event(Event::Start(BytesStart::new("content")))?;
+                write_text(writer, entity.body.trim(), options.escape)?;
+                writer.write_event(Event::End(BytesEnd::new("content")))?;
+            }
         }
 
-        write_node(writer, child_node)?;
-        writer.write_event(Event::End(BytesEnd::new(name)))?;
+        write_node(writer, child_node, options, requires)?;
+        writer.write_event(Event::End(end))?;
+    }
+
+    Ok(())
+}
+
+/// Starts an element named `name`, or `<fallback name="...">` when `name` is not a
+/// valid XML element name (e.g. it starts with a digit or contains spaces).
+fn start_element<'a>(name: &'a str, fallback: &'a str) -> BytesStart<'a> {
+    if is_valid_xml_name(name) {
+        BytesStart::new(name)
+    } else {
+        let mut elem = BytesStart::new(fallback);
+        elem.push_attribute(("name", name));
+        elem
+    }
+}
+
+/// Checks `name` against the XML `NCName` production (a `Name` without colons, so
+/// namespace-aware parsers do not treat part of it as a prefix).
+fn is_valid_xml_name(name: &str) -> bool {
+    fn is_name_start_char(c: char) -> bool {
+        matches!(c,
+            'A'..='Z' | '_' | 'a'..='z'
+            | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{2FF}'
+            | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}' | '\u{200C}'..='\u{200D}'
+            | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}'
+            | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}' | '\u{10000}'..='\u{EFFFF}')
+    }
+    fn is_name_char(c: char) -> bool {
+        is_name_start_char(c)
+            || matches!(c,
+                '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+    }
+
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if is_name_start_char(first) => chars.all(is_name_char),
+        _ => false,
     }
+}
 
+fn write_directions<W: Write>(
+    writer: &mut Writer<W>,
+    body: &str,
+    mode: EscapeMode,
+) -> Result<(), PersonaError> {
+    writer.write_event(Event::Start(BytesStart::new("directions")))?;
+    match mode {
+        // Directions have always been escaped, so the compatibility mode keeps doing that.
+        EscapeMode::Raw => write_text(writer, body.trim(), EscapeMode::Escape)?,
+        _ => write_text(writer, body.trim(), mode)?,
+    }
+    writer.write_event(Event::End(BytesEnd::new("directions")))?;
+    Ok(())
+}
+
+fn write_text<W: Write>(
+    writer: &mut Writer<W>,
+    text: &str,
+    mode: EscapeMode,
+) -> Result<(), PersonaError> {
+    match mode {
+        EscapeMode::Escape => writer.write_event(Event::Text(BytesText::new(text)))?,
+        EscapeMode::Raw => writer.write_event(Event::Text(BytesText::from_escaped(text)))?,
+        EscapeMode::Cdata => {
+            if text.is_empty() {
+                return Ok(());
+            }
+            // "]]>" cannot appear inside a CDATA section, so split it across two sections.
+            let mut rest = text;
+            while let Some(idx) = rest.find("]]>") {
+                writer.write_event(Event::CData(BytesCData::new(&rest[..idx + 2])))?;
+                rest = &rest[idx + 2..];
+            }
+            writer.write_event(Event::CData(BytesCData::new(rest)))?;
+        }
+    }
     Ok(())
 }
 
 fn write_yaml_value<W: Write>(
     writer: &mut Writer<W>,
     value: &serde_yaml::Value,
+    mode: EscapeMode,
 ) -> Result<(), PersonaError> {
     match value {
         serde_yaml::Value::Mapping(map) => {
             for (k, v) in map {
-                let key_str = k.as_str().ok_or_else(|| {
-                    PersonaError::Serialization("YAML key must be a string".to_string())
-                })?;
-
-                // XML tags must be valid names. Assuming keys are valid.
-                let elem = BytesStart::new(key_str);
-                writer.write_event(Event::Start(elem.clone()))?;
-                write_yaml_value(writer, v)?;
-                writer.write_event(Event::End(BytesEnd::new(key_str)))?;
+                let key_str = match k {
+                    serde_yaml::Value::String(s) => s.clone(),
+                    serde_yaml::Value::Number(n) => n.to_string(),
+                    serde_yaml::Value::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(PersonaError::Serialization(
+                            "YAML key must be a string, number or boolean".to_string(),
+                        ));
+                    }
+                };
+
+                let elem = start_element(&key_str, "field");
+                let end = elem.to_end().into_owned();
+                writer.write_event(Event::Start(elem))?;
+                write_yaml_value(writer, v, mode)?;
+                writer.write_event(Event::End(end))?;
             }
         }
         serde_yaml::Value::String(s) => {
-            writer.write_event(Event::Text(BytesText::from_escaped(s)))?;
+            write_text(writer, s, mode)?;
         }
         serde_yaml::Value::Number(n) => {
-            writer.write_event(Event::Text(BytesText::from_escaped(n.to_string())))?;
+            write_text(writer, &n.to_string(), mode)?;
         }
         serde_yaml::Value::Bool(b) => {
-            writer.write_event(Event::Text(BytesText::from_escaped(b.to_string())))?;
+            write_text(writer, &b.to_string(), mode)?;
         }
         serde_yaml::Value::Sequence(seq) => {
             for item in seq {
                 let elem = BytesStart::new("item");
                 writer.write_event(Event::Start(elem.clone()))?;
-                write_yaml_value(writer, item)?;
+                write_yaml_value(writer, item, mode)?;
                 writer.write_event(Event::End(BytesEnd::new("item")))?;
             }
         }
@@ -193,6 +333,7 @@ fn write_yaml_value<W: Write>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Header;
     use persona_parser::{Frontmatter, ParsedEntity};
     use serde_yaml::Mapping;
     use std::path::PathBuf;
@@ -213,6 +354,7 @@ mod tests {
                 name: "python-helper".to_string(),
                 description: "Assists with Python coding tasks.".to_string(),
                 other: serde_yaml::Value::Mapping(entity1_other),
+                ..Default::default()
             },
             body: "".to_string(),
             char_count: 0,
@@ -230,6 +372,7 @@ mod tests {
                 name: "writer".to_string(),
                 description: "A creative writing assistant.".to_string(),
                 other: serde_yaml::Value::Mapping(entity2_other),
+                ..Default::default()
             },
             body: "".to_string(),
             char_count: 0,
@@ -243,7 +386,7 @@ mod tests {
         let expected_xml = r#"<persona-context>
   <personas>
     <creative>
-      <writer path="./personas/creative/writer/PERSONA.md">
+      <writer path="personas/creative/writer/PERSONA.md">
         <description>A creative writing assistant.</description>
         <tone>Inspirational</tone>
       </writer>
@@ -251,7 +394,7 @@ mod tests {
   </personas>
   <skills>
     <coding>
-      <python-helper path="./skills/coding/python-helper/SKILL.md">
+      <python-helper path="skills/coding/python-helper/SKILL.md">
         <description>Assists with Python coding tasks.</description>
         <license>MIT</license>
       </python-helper>
@@ -261,31 +404,346 @@ mod tests {
         assert_eq!(xml, expected_xml);
     }
 
-    #[test]
-    fn test_generate_xml_escaping() {
-        let inputs = vec![PathBuf::from(".")];
+    fn special_entity() -> EntityOrHeader {
         let mut other = Mapping::new();
         other.insert(
             serde_yaml::Value::String("special".to_string()),
             serde_yaml::Value::String("<&>\"'".to_string()),
         );
 
-        let entity = EntityOrHeader::Entity(ParsedEntity {
+        EntityOrHeader::Entity(ParsedEntity {
             path: PathBuf::from("category/entity/ENTITY.md"),
             frontmatter: Frontmatter {
                 name: "entity".to_string(),
-                description: "Test & check < >".to_string(),
+                description: "Test & check < > ]]> done".to_string(),
                 other: serde_yaml::Value::Mapping(other),
+                ..Default::default()
             },
             body: "".to_string(),
             char_count: 0,
-        });
+        })
+    }
 
-        let xml = generate_xml(&[entity], &inputs, None).unwrap();
+    /// Parses `xml` and returns the text content of every element named `tag`.
+    fn parse_texts(xml: &str, tag: &str) -> Vec<String> {
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_str(xml);
+        reader.check_end_names(true);
+        let mut texts = Vec::new();
+        let mut current: Option<String> = None;
+        loop {
+            match reader
+                .read_event()
+                .expect("AGENTS.md must be well-formed XML")
+            {
+                Event::Start(e) if e.name().as_ref() == tag.as_bytes() => {
+                    current = Some(String::new());
+                }
+                Event::Text(t) => {
+                    if let Some(c) = current.as_mut() {
+                        c.push_str(&t.unescape().unwrap());
+                    }
+                }
+                Event::CData(t) => {
+                    if let Some(c) = current.as_mut() {
+                        c.push_str(std::str::from_utf8(&t).unwrap());
+                    }
+                }
+                Event::End(e) if e.name().as_ref() == tag.as_bytes() => {
+                    texts.extend(current.take());
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        texts
+    }
+
+    #[test]
+    fn test_generate_xml_escaping() {
+        let inputs = vec![PathBuf::from(".")];
+
+        let xml = generate_xml(&[special_entity()], &inputs, None).unwrap();
+
+        assert!(xml.contains("&lt;&amp;&gt;&quot;&apos;"));
+        assert!(xml.contains("Test &amp; check &lt; &gt; ]]&gt; done"));
+    }
+
+    #[test]
+    fn test_generate_xml_cdata() {
+        let inputs = vec![PathBuf::from(".")];
+        let options = XmlOptions {
+            escape: EscapeMode::Cdata,
+            ..Default::default()
+        };
+
+        let xml = generate_xml_with_options(&[special_entity()], &inputs, None, &options).unwrap();
+
+        assert!(xml.contains("<special><![CDATA[<&>\"']]></special>"));
+        assert!(xml.contains("<![CDATA[Test & check < > ]]]]><![CDATA[> done]]>"));
+    }
+
+    #[test]
+    fn test_generate_xml_raw_compatibility() {
+        let inputs = vec![PathBuf::from(".")];
+        let options = XmlOptions {
+            escape: EscapeMode::Raw,
+            ..Default::default()
+        };
+
+        let xml = generate_xml_with_options(&[special_entity()], &inputs, None, &options).unwrap();
 
         // Should NOT escape
         assert!(xml.contains("<&>\"'"));
-        assert!(xml.contains("Test & check < >"));
+        assert!(xml.contains("Test & check < > ]]> done"));
+    }
+
+    #[test]
+    fn test_generate_xml_round_trip() {
+        let inputs = vec![PathBuf::from(".")];
+        let header = EntityOrHeader::Header(Header {
+            path: PathBuf::from("category/HEADER.md"),
+            body: "Use <these> & those".to_string(),
+            ..Default::default()
+        });
+        let items = vec![header, special_entity()];
+
+        for escape in [EscapeMode::Escape, EscapeMode::Cdata] {
+            let options = XmlOptions {
+                escape,
+                ..Default::default()
+            };
+            let xml = generate_xml_with_options(&items, &inputs, Some("Root & <root>"), &options)
+                .unwrap();
+
+            assert_eq!(
+                parse_texts(&xml, "description"),
+                vec!["Test & check < > ]]> done"],
+                "{:?}",
+                escape
+            );
+            assert_eq!(parse_texts(&xml, "special"), vec!["<&>\"'"], "{:?}", escape);
+            assert_eq!(
+                parse_texts(&xml, "directions"),
+                vec!["Root & <root>", "Use <these> & those"],
+                "{:?}",
+                escape
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_valid_xml_name() {
+        assert!(is_valid_xml_name("python-helper"));
+        assert!(is_valid_xml_name("_private.key"));
+        assert!(is_valid_xml_name("café"));
+        assert!(!is_valid_xml_name(""));
+        assert!(!is_valid_xml_name("1password"));
+        assert!(!is_valid_xml_name("-leading"));
+        assert!(!is_valid_xml_name("my key"));
+        assert!(!is_valid_xml_name("ns:key"));
+    }
+
+    #[test]
+    fn test_generate_xml_invalid_names_use_fallback_elements() {
+        let inputs = vec![PathBuf::from(".")];
+        let mut other = Mapping::new();
+        other.insert("my key".into(), "spaced".into());
+        other.insert(serde_yaml::Value::Number(2024.into()), "year".into());
+        let mut nested = Mapping::new();
+        nested.insert("a&b".into(), "amp".into());
+        other.insert("nested".into(), serde_yaml::Value::Mapping(nested));
+
+        let entity = EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from("2024 archive/1password/SKILL.md"),
+            frontmatter: Frontmatter {
+                name: "1password".to_string(),
+                description: "Password manager".to_string(),
+                other: serde_yaml::Value::Mapping(other),
+                ..Default::default()
+            },
+            body: "".to_string(),
+            char_count: 0,
+        });
+
+        let xml = generate_xml(&[entity], &inputs, None).unwrap();
+
+        let expected_xml = r#"<persona-context>
+  <category name="2024 archive">
+    <entity name="1password" path="2024 archive/1password/SKILL.md">
+      <description>Password manager</description>
+      <field name="my key">spaced</field>
+      <field name="2024">year</field>
+      <nested>
+        <field name="a&amp;b">amp</field>
+      </nested>
+    </entity>
+  </category>
+</persona-context>"#;
+        assert_eq!(xml, expected_xml);
+        assert_eq!(parse_texts(&xml, "description"), vec!["Password manager"]);
+    }
+
+    #[test]
+    fn test_generate_xml_leaves_out_when() {
+        let frontmatter: Frontmatter = serde_yaml::from_str(
+            "name: jj\ndescription: Jujutsu\nlicense: MIT\nwhen:\n  binary: jj",
+        )
+        .unwrap();
+        let entity = EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from("skills/jj/SKILL.md"),
+            frontmatter,
+            body: "".to_string(),
+            char_count: 0,
+        });
+
+        let xml = generate_xml(&[entity], &[PathBuf::from(".")], None).unwrap();
+        assert!(xml.contains("<license>MIT</license>"));
+        assert!(!xml.contains("when") && !xml.contains("binary"));
+    }
+
+    fn body_entity(name: &str, body: &str, inline: Option<bool>) -> EntityOrHeader {
+        EntityOrHeader::Entity(ParsedEntity {
+            path: PathBuf::from(format!("rules/{name}/RULE.md")),
+            frontmatter: Frontmatter {
+                name: name.to_string(),
+                description: format!("{name} rule"),
+                inline,
+                ..Default::default()
+            },
+            body: format!("\n{body}\n"),
+            char_count: 0,
+        })
+    }
+
+    #[test]
+    fn test_generate_xml_inline_flag() {
+        let inputs = vec![PathBuf::from(".")];
+        let items = vec![
+            body_entity("always", "Always <do> this.", Some(true)),
+            body_entity("reference", "Read me on demand.", None),
+        ];
+
+        let xml = generate_xml(&items, &inputs, None).unwrap();
+
+        assert!(xml.contains(
+            "<description>always rule</description>\n      <content>Always &lt;do&gt; this.</content>"
+        ));
+        assert!(!xml.contains("Read me on demand."));
+        assert!(!xml.contains("<inline>"));
+    }
+
+    #[test]
+    fn test_generate_xml_requires() {
+        let inputs = vec![PathBuf::from(".")];
+        let mut dev = body_entity("dev", "Develop.", None);
+        let mut old = body_entity("old", "Old.", None);
+        if let EntityOrHeader::Entity(e) = &mut dev {
+            e.frontmatter.requires = vec!["rules/always".to_string()];
+            e.frontmatter.tags = vec!["backend".to_string()];
+        }
+        if let EntityOrHeader::Entity(e) = &mut old {
+            e.frontmatter.deprecated = true;
+        }
+        let items = vec![dev, old, body_entity("always", "Always.", None)];
+
+        let xml = generate_xml(&items, &inputs, None).unwrap();
+
+        assert!(xml.contains(
+            "<description>dev rule</description>\n      <requires>\n        <item path=\"rules/always/RULE.md\">always</item>\n      </requires>\n      <tags>\n        <item>backend</item>\n      </tags>"
+        ));
+        assert!(
+            xml.contains(
+                "<description>old rule</description>\n      <deprecated>true</deprecated>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_generate_xml_inline_threshold() {
+        let inputs = vec![PathBuf::from(".")];
+        let items = vec![
+            body_entity("small", "Tiny.", None),
+            body_entity("large", &"word ".repeat(100), None),
+            body_entity("opt-out", "Tiny too.", Some(false)),
+        ];
+        let options = XmlOptions {
+            inline_max_tokens: Some(10),
+            ..Default::default()
+        };
+
+        let xml = generate_xml_with_options(&items, &inputs, None, &options).unwrap();
+
+        assert!(xml.contains("<content>Tiny.</content>"));
+        assert!(!xml.contains("word word"));
+        assert!(!xml.contains("Tiny too."));
+    }
+
+    #[test]
+    fn test_generate_xml_paths_relative_to_output() {
+        let build = |root: &Path| {
+            let inputs = vec![root.join(".agent")];
+            let items = vec![EntityOrHeader::Entity(ParsedEntity {
+                path: root.join(".agent/skills/coding/rust/SKILL.md"),
+                frontmatter: Frontmatter {
+                    name: "rust".to_string(),
+                    description: "Rust Skill".to_string(),
+                    ..Default::default()
+                },
+                body: "".to_string(),
+                char_count: 0,
+            })];
+            let options = XmlOptions {
+                base_dir: Some(root.to_path_buf()),
+                ..Default::default()
+            };
+            generate_xml_with_options(&items, &inputs, None, &options).unwrap()
+        };
+
+        let first = build(Path::new("/home/alice/project"));
+        let second = build(Path::new("/build/source"));
+
+        assert!(first.contains(r#"<rust path=".agent/skills/coding/rust/SKILL.md">"#));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_generate_xml_explicit_order() {
+        let inputs = vec![PathBuf::from(".")];
+        let entity = |path: &str, name: &str, order: Option<i64>| {
+            EntityOrHeader::Entity(ParsedEntity {
+                path: PathBuf::from(path),
+                frontmatter: Frontmatter {
+                    name: name.to_string(),
+                    description: name.to_string(),
+                    order,
+                    ..Default::default()
+                },
+                body: "".to_string(),
+                char_count: 0,
+            })
+        };
+        let items = vec![
+            entity("rules/alpha/RULE.md", "alpha", None),
+            entity("rules/zulu/RULE.md", "zulu", Some(1)),
+            entity("rules/beta/RULE.md", "beta", None),
+            entity("rules/read-first/RULE.md", "read-first", Some(0)),
+            entity("skills/rust/SKILL.md", "rust", None),
+            EntityOrHeader::Header(Header {
+                path: PathBuf::from("skills/HEADER.md"),
+                body: "Skills".to_string(),
+                order: Some(-1),
+            }),
+        ];
+
+        let xml = generate_xml(&items, &inputs, None).unwrap();
+
+        let position = |needle: &str| xml.find(needle).unwrap();
+        assert!(position("<skills>") < position("<rules>"));
+        assert!(position("<read-first") < position("<zulu"));
+        assert!(position("<zulu") < position("<alpha"));
+        assert!(position("<alpha") < position("<beta"));
     }
 
     #[test]
@@ -296,6 +754,7 @@ mod tests {
         let header = EntityOrHeader::Header(Header {
             path: PathBuf::from("./skills/coding/HEADER.md"),
             body: "Coding Category Description".to_string(),
+            ..Default::default()
         });
 
         // Represents skills/coding/rust/SKILL.md
@@ -305,6 +764,7 @@ mod tests {
                 name: "rust".to_string(),
                 description: "Rust Skill".to_string(),
                 other: serde_yaml::Value::Mapping(Mapping::new()),
+                ..Default::default()
             },
             body: "".to_string(),
             char_count: 0,
@@ -326,7 +786,7 @@ mod tests {
 
         assert!(xml.contains("<coding>"));
         assert!(xml.contains("<directions>Coding Category Description</directions>"));
-        assert!(xml.contains("<rust path=\"./skills/coding/rust/SKILL.md\">"));
+        assert!(xml.contains("<rust path=\"skills/coding/rust/SKILL.md\">"));
         assert!(xml.contains("<description>Rust Skill</description>"));
     }
 }
@@ -0,0 +1,578 @@
+//! Reads a generated agents file back into an element tree so it can be compared
+//! structurally against freshly generated output.
+use crate::PersonaError;
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// An element of a parsed agents file.
+///
+/// Text is whitespace-normalised (trimmed, runs collapsed to one space, except inside
+/// `<content>`) and CDATA sections are merged with regular text, so formatting differences
+/// do not matter.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XmlElement {
+    pub tag: String,
+    pub attributes: BTreeMap<String, String>,
+    pub text: String,
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// The logical name: the `name` attribute of fallback elements (`<entity name="...">`,
+    /// `<category name="...">`, `<field name="...">`), otherwise the tag.
+    pub fn name(&self) -> &str {
+        match self.tag.as_str() {
+            "entity" | "category" | "field" => self
+                .attributes
+                .get("name")
+                .map(String::as_str)
+                .unwrap_or(&self.tag),
+            _ => &self.tag,
+        }
+    }
+}
+
+/// What differs at an element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// An element present in the expected output is missing from the current file.
+    Missing,
+    /// The current file contains an element that is not in the expected output.
+    Unexpected,
+    /// An element's text differs.
+    Text { current: String, expected: String },
+    /// An attribute differs (`None` when absent).
+    Attribute {
+        name: String,
+        current: Option<String>,
+        expected: Option<String>,
+    },
+    /// The same children appear in a different order.
+    Order {
+        current: Vec<String>,
+        expected: Vec<String>,
+    },
+}
+
+/// A logical difference between two agents files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlDifference {
+    /// The `path` attribute of the entity the difference is in; `None` for categories and
+    /// the document root.
+    pub entity: Option<String>,
+    /// Element path, starting at the entity element inside an entity (e.g.
+    /// `rust/description`), else at the root.
+    pub path: String,
+    pub change: Change,
+}
+
+impl XmlDifference {
+    /// The difference without its entity, for output grouped by entity.
+    pub fn detail(&self) -> Detail<'_> {
+        Detail(self)
+    }
+}
+
+impl fmt::Display for XmlDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(entity) = &self.entity {
+            write!(f, "{}: ", entity)?;
+        }
+        write!(f, "{}", self.detail())
+    }
+}
+
+/// See [`XmlDifference::detail`].
+pub struct Detail<'a>(&'a XmlDifference);
+
+impl fmt::Display for Detail<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = &self.0.path;
+        match &self.0.change {
+            Change::Missing => write!(f, "{}: missing", path),
+            Change::Unexpected => write!(f, "{}: unexpected element", path),
+            Change::Text { current, expected } => match first_different_line(current, expected) {
+                Some((line, current, expected)) => write!(
+                    f,
+                    "{}: line {} is '{}', expected '{}'",
+                    path, line, current, expected
+                ),
+                None => write!(
+                    f,
+                    "{}: text is '{}', expected '{}'",
+                    path, current, expected
+                ),
+            },
+            Change::Attribute {
+                name,
+                current,
+                expected,
+            } => write!(
+                f,
+                "{}: attribute '{}' is {}, expected {}",
+                path,
+                name,
+                display_option(current),
+                display_option(expected)
+            ),
+            Change::Order { current, expected } => write!(
+                f,
+                "{}: children are ordered [{}], expected [{}]",
+                path,
+                current.join(", "),
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+/// For multi-line text, the first line (1-based) that differs and both versions of it.
+fn first_different_line<'a>(
+    current: &'a str,
+    expected: &'a str,
+) -> Option<(usize, &'a str, &'a str)> {
+    if !current.contains('\n') && !expected.contains('\n') {
+        return None;
+    }
+    let mut current_lines = current.lines();
+    let mut expected_lines = expected.lines();
+    for line in 1.. {
+        match (current_lines.next(), expected_lines.next()) {
+            (None, None) => return None,
+            (a, b) if a != b => return Some((line, a.unwrap_or_default(), b.unwrap_or_default())),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn display_option(value: &Option<String>) -> String {
+    match value {
+        Some(v) => format!("'{}'", v),
+        None => "absent".to_string(),
+    }
+}
+
+/// Parses an agents file into its root element.
+pub fn parse_xml(content: &str) -> Result<XmlElement, PersonaError> {
+    let mut reader = Reader::from_str(content);
+    reader.check_end_names(true);
+
+    let mut stack: Vec<XmlElement> = Vec::new();
+    let mut root = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => stack.push(start_element(&e)?),
+            Event::Empty(e) => {
+                let element = start_element(&e)?;
+                close_element(element, &mut stack, &mut root)?;
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or_else(|| {
+                    PersonaError::Serialization("Unbalanced closing tag".to_string())
+                })?;
+                close_element(element, &mut stack, &mut root)?;
+            }
+            Event::Text(t) => {
+                let text = t.unescape()?;
+                append_text(&mut stack, &text)?;
+            }
+            Event::CData(t) => {
+                let bytes = t.into_inner();
+                let text = std::str::from_utf8(&bytes)
+                    .map_err(|e| PersonaError::Serialization(e.to_string()))?;
+                append_text(&mut stack, text)?;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(PersonaError::Serialization(format!(
+            "Unclosed element <{}>",
+            stack.last().map(|e| e.tag.as_str()).unwrap_or_default()
+        )));
+    }
+    let mut root = root
+        .ok_or_else(|| PersonaError::Serialization("Document has no root element".to_string()))?;
+    normalise(&mut root);
+    Ok(root)
+}
+
+fn start_element(e: &BytesStart) -> Result<XmlElement, PersonaError> {
+    let tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
+    let mut attributes = BTreeMap::new();
+    for attr in e.attributes() {
+        let attr = attr.map_err(|e| PersonaError::Serialization(e.to_string()))?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+        attributes.insert(key, attr.unescape_value()?.to_string());
+    }
+    Ok(XmlElement {
+        tag,
+        attributes,
+        ..Default::default()
+    })
+}
+
+fn close_element(
+    element: XmlElement,
+    stack: &mut [XmlElement],
+    root: &mut Option<XmlElement>,
+) -> Result<(), PersonaError> {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None if root.is_none() => *root = Some(element),
+        None => {
+            return Err(PersonaError::Serialization(
+                "Document has more than one root element".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn append_text(stack: &mut [XmlElement], text: &str) -> Result<(), PersonaError> {
+    match stack.last_mut() {
+        Some(element) => element.text.push_str(text),
+        None if text.trim().is_empty() => {}
+        None => {
+            return Err(PersonaError::Serialization(
+                "Text outside of the root element".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Inlined `<content>` bodies are Markdown, where line breaks and indentation matter: only
+/// their ends are trimmed.
+fn normalise(element: &mut XmlElement) {
+    element.text = if element.tag == "content" {
+        element.text.trim().to_string()
+    } else {
+        element
+            .text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    for child in &mut element.children {
+        normalise(child);
+    }
+}
+
+/// Compares the `current` file against the `expected` output and lists the logical
+/// differences. Children are matched by logical name (and position among equally
+/// named siblings), so a change is reported once, at the entity and element it affects.
+pub fn compare_xml(current: &XmlElement, expected: &XmlElement) -> Vec<XmlDifference> {
+    let mut differences = Vec::new();
+    if current.name() != expected.name() {
+        for (element, change) in [(current, Change::Unexpected), (expected, Change::Missing)] {
+            differences.push(XmlDifference {
+                entity: entity_of(element),
+                path: element.name().to_string(),
+                change,
+            });
+        }
+        return differences;
+    }
+    compare_element(
+        current,
+        expected,
+        &Location {
+            entity: entity_of(expected),
+            path: expected.name().to_string(),
+        },
+        &mut differences,
+    );
+    differences
+}
+
+/// Where an element is: its entity, if any, and its path from there.
+struct Location {
+    entity: Option<String>,
+    path: String,
+}
+
+impl Location {
+    fn difference(&self, change: Change) -> XmlDifference {
+        XmlDifference {
+            entity: self.entity.clone(),
+            path: self.path.clone(),
+            change,
+        }
+    }
+
+    /// The location of a child; an entity element starts a new location.
+    fn child(&self, key: &(String, usize), element: &XmlElement) -> Location {
+        match entity_of(element) {
+            Some(entity) => Location {
+                entity: Some(entity),
+                path: element.name().to_string(),
+            },
+            None => Location {
+                entity: self.entity.clone(),
+                path: child_path(&self.path, key),
+            },
+        }
+    }
+}
+
+/// Entity elements carry the `path` of their definition file and a description; required
+/// `<item>`s carry a path too, but no children.
+fn entity_of(element: &XmlElement) -> Option<String> {
+    if !element.children.iter().any(|c| c.tag == "description") {
+        return None;
+    }
+    element.attributes.get("path").cloned()
+}
+
+fn compare_element(
+    current: &XmlElement,
+    expected: &XmlElement,
+    location: &Location,
+    differences: &mut Vec<XmlDifference>,
+) {
+    let names: std::collections::BTreeSet<&String> = current
+        .attributes
+        .keys()
+        .chain(expected.attributes.keys())
+        .collect();
+    for name in names {
+        let current_value = current.attributes.get(name);
+        let expected_value = expected.attributes.get(name);
+        if current_value != expected_value {
+            differences.push(location.difference(Change::Attribute {
+                name: name.clone(),
+                current: current_value.cloned(),
+                expected: expected_value.cloned(),
+            }));
+        }
+    }
+
+    if current.text != expected.text {
+        differences.push(location.difference(Change::Text {
+            current: current.text.clone(),
+            expected: expected.text.clone(),
+        }));
+    }
+
+    let current_keys = child_keys(current);
+    let expected_keys = child_keys(expected);
+    let current_by_key: BTreeMap<&(String, usize), &XmlElement> =
+        current_keys.iter().zip(&current.children).collect();
+
+    for (key, expected_child) in expected_keys.iter().zip(&expected.children) {
+        let child = location.child(key, expected_child);
+        match current_by_key.get(key) {
+            Some(current_child) => {
+                compare_element(current_child, expected_child, &child, differences)
+            }
+            None => differences.push(child.difference(Change::Missing)),
+        }
+    }
+    for (key, current_child) in current_keys.iter().zip(&current.children) {
+        if !expected_keys.contains(key) {
+            differences.push(
+                location
+                    .child(key, current_child)
+                    .difference(Change::Unexpected),
+            );
+        }
+    }
+
+    let common_current: Vec<&(String, usize)> = current_keys
+        .iter()
+        .filter(|k| expected_keys.contains(k))
+        .collect();
+    let common_expected: Vec<&(String, usize)> = expected_keys
+        .iter()
+        .filter(|k| current_keys.contains(k))
+        .collect();
+    if common_current != common_expected {
+        differences.push(location.difference(Change::Order {
+            current: common_current.iter().map(|k| k.0.clone()).collect(),
+            expected: common_expected.iter().map(|k| k.0.clone()).collect(),
+        }));
+    }
+}
+
+/// Keys children by logical name plus their index among siblings with the same name.
+fn child_keys(element: &XmlElement) -> Vec<(String, usize)> {
+    let mut seen: BTreeMap<&str, usize> = BTreeMap::new();
+    element
+        .children
+        .iter()
+        .map(|child| {
+            let count = seen.entry(child.name()).or_default();
+            let key = (child.name().to_string(), *count);
+            *count += 1;
+            key
+        })
+        .collect()
+}
+
+fn child_path(parent: &str, key: &(String, usize)) -> String {
+    if key.1 == 0 {
+        format!("{}/{}", parent, key.0)
+    } else {
+        format!("{}/{}[{}]", parent, key.0, key.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPECTED: &str = r#"<persona-context>
+  <skills>
+    <rust path="skills/rust/SKILL.md" kind="skill">
+      <description>Rust programming skill</description>
+      <compatibility>
+        <item>cargo</item>
+        <item>rustup</item>
+      </compatibility>
+    </rust>
+    <entity name="1password" path="skills/1password/SKILL.md">
+      <description>Passwords &amp; secrets</description>
+    </entity>
+  </skills>
+</persona-context>"#;
+
+    #[test]
+    fn test_parse_xml() {
+        let root = parse_xml(EXPECTED).unwrap();
+
+        assert_eq!(root.tag, "persona-context");
+        let skills = &root.children[0];
+        let rust = &skills.children[0];
+        assert_eq!(rust.attributes["path"], "skills/rust/SKILL.md");
+        assert_eq!(rust.children[0].text, "Rust programming skill");
+        assert_eq!(rust.children[1].children.len(), 2);
+        assert_eq!(skills.children[1].name(), "1password");
+        assert_eq!(skills.children[1].children[0].text, "Passwords & secrets");
+    }
+
+    #[test]
+    fn test_parse_xml_rejects_malformed() {
+        assert!(parse_xml("<a><b></a>").is_err());
+        assert!(parse_xml("<a></a><b></b>").is_err());
+        assert!(parse_xml("INVALID CONTENT").is_err());
+    }
+
+    #[test]
+    fn test_compare_xml_ignores_formatting() {
+        let reformatted = r#"<persona-context><skills>
+<rust kind="skill"   path="skills/rust/SKILL.md"><description>
+    Rust   programming
+    skill
+</description><compatibility><item><![CDATA[cargo]]></item><item>rustup</item></compatibility></rust>
+<entity path="skills/1password/SKILL.md" name="1password"><description><![CDATA[Passwords & secrets]]></description></entity>
+</skills></persona-context>
+"#;
+
+        let current = parse_xml(reformatted).unwrap();
+        let expected = parse_xml(EXPECTED).unwrap();
+
+        assert_eq!(compare_xml(&current, &expected), vec![]);
+    }
+
+    #[test]
+    fn test_compare_xml_reports_differences() {
+        let edited = EXPECTED
+            .replace("Rust programming skill", "Rust skill")
+            .replace(r#" kind="skill""#, "")
+            .replace("        <item>rustup</item>\n", "")
+            .replace("</rust>", "  <license>MIT</license>\n    </rust>");
+
+        let current = parse_xml(&edited).unwrap();
+        let expected = parse_xml(EXPECTED).unwrap();
+        let differences = compare_xml(&current, &expected);
+
+        let rust = |path: &str, change| XmlDifference {
+            entity: Some("skills/rust/SKILL.md".to_string()),
+            path: path.to_string(),
+            change,
+        };
+        assert_eq!(
+            differences,
+            vec![
+                rust(
+                    "rust",
+                    Change::Attribute {
+                        name: "kind".to_string(),
+                        current: None,
+                        expected: Some("skill".to_string()),
+                    }
+                ),
+                rust(
+                    "rust/description",
+                    Change::Text {
+                        current: "Rust skill".to_string(),
+                        expected: "Rust programming skill".to_string(),
+                    }
+                ),
+                rust("rust/compatibility/item[1]", Change::Missing),
+                rust("rust/license", Change::Unexpected),
+            ]
+        );
+        assert_eq!(
+            differences[1].to_string(),
+            "skills/rust/SKILL.md: rust/description: text is 'Rust skill', expected 'Rust programming skill'"
+        );
+    }
+
+    #[test]
+    fn test_compare_xml_keeps_content_whitespace() {
+        let with_content = |body: &str| {
+            format!(
+                "<persona-context><rust path=\"skills/rust/SKILL.md\"><description>Rust</description><content>{}</content></rust></persona-context>",
+                body
+            )
+        };
+        let expected =
+            parse_xml(&with_content("\n```\nfn main() {\n    run();\n}\n```\n")).unwrap();
+
+        // Only the ends of a body are trimmed.
+        let trimmed = parse_xml(&with_content("```\nfn main() {\n    run();\n}\n```")).unwrap();
+        assert_eq!(compare_xml(&trimmed, &expected), vec![]);
+
+        let dedented = parse_xml(&with_content("```\nfn main() {\nrun();\n}\n```")).unwrap();
+        let differences = compare_xml(&dedented, &expected);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(
+            differences[0].to_string(),
+            "skills/rust/SKILL.md: rust/content: line 3 is 'run();', expected '    run();'"
+        );
+
+        let joined = parse_xml(&with_content("```\nfn main() { run(); }\n```")).unwrap();
+        assert_eq!(compare_xml(&joined, &expected).len(), 1);
+    }
+
+    #[test]
+    fn test_compare_xml_reports_order() {
+        let reordered = r#"<persona-context><skills>
+<entity name="1password" path="skills/1password/SKILL.md"><description>Passwords &amp; secrets</description></entity>
+<rust path="skills/rust/SKILL.md" kind="skill"><description>Rust programming skill</description>
+<compatibility><item>cargo</item><item>rustup</item></compatibility></rust>
+</skills></persona-context>"#;
+
+        let current = parse_xml(reordered).unwrap();
+        let expected = parse_xml(EXPECTED).unwrap();
+
+        assert_eq!(
+            compare_xml(&current, &expected),
+            vec![XmlDifference {
+                entity: None,
+                path: "persona-context/skills".to_string(),
+                change: Change::Order {
+                    current: vec!["1password".to_string(), "rust".to_string()],
+                    expected: vec!["rust".to_string(), "1password".to_string()],
+                },
+            }]
+        );
+    }
+}
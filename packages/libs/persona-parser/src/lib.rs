@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -27,17 +28,101 @@ pub enum PersonaError {
     EmptyBody,
     #[error("Parent directory not found for path: {0}")]
     ParentDirNotFound(String),
+    #[error(
+        "Invalid frontmatter key: {0}. Keys must be strings, numbers or booleans without control characters."
+    )]
+    InvalidFrontmatterKey(String),
+    #[error("Unsupported frontmatter value for key '{0}': tagged YAML values are not supported")]
+    UnsupportedFrontmatterValue(String),
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// How an entity in a later input changes the entity at the same path in earlier inputs.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// Replace the earlier entity.
+    Override,
+    /// Merge this frontmatter into the earlier entity.
+    Extend,
+    /// Drop the earlier entity.
+    Remove,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
 pub struct Frontmatter {
     pub name: String,
+    /// Required, except for entities that extend or remove an earlier one.
+    #[serde(default)]
     pub description: String,
+    /// Inline the body into the generated agents file (`true`) or never inline it (`false`).
+    /// When unset, the global inlining threshold decides.
+    #[serde(default)]
+    pub inline: Option<bool>,
+    /// Position among sibling entities and categories. Lower values come first; entities
+    /// without an order follow in alphabetical order.
+    #[serde(default, alias = "priority")]
+    pub order: Option<i64>,
+    /// Entities this one depends on, by name or path, loaded together with it.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub requires: Vec<String>,
+    /// Persona entities only: the skills bundled with the persona, by name or path.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub skills: Vec<String>,
+    /// Labels for selecting entities by tag.
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub tags: Vec<String>,
+    /// Kept for existing users only; no entity may require it.
+    #[serde(default)]
+    pub deprecated: bool,
+    /// Layering directive towards the entity at the same path in earlier inputs.
+    #[serde(default)]
+    pub layer: Option<Layer>,
+    /// Conditions under which the entity is built, as written; read by the core crate.
+    #[serde(default)]
+    pub when: Option<serde_yaml::Value>,
     #[serde(flatten)]
     pub other: serde_yaml::Value,
 }
 
-#[derive(Debug)]
+/// Reads a list of strings, taking a single string as a list of one and `null` as empty.
+pub fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    struct OneOrMany;
+
+    impl<'de> serde::de::Visitor<'de> for OneOrMany {
+        type Value = Vec<String>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string or a list of strings")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(vec![value.to_string()])
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Vec::new())
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut values = Vec::new();
+            while let Some(value) = seq.next_element()? {
+                values.push(value);
+            }
+            Ok(values)
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany)
+}
+
+#[derive(Debug, Clone)]
 pub struct ParsedEntity {
     pub path: PathBuf,
     pub frontmatter: Frontmatter,
@@ -45,6 +130,22 @@ pub struct ParsedEntity {
     pub char_count: usize,
 }
 
+impl ParsedEntity {
+    /// Approximate token count of the body (about 5 characters per token).
+    pub fn body_tokens(&self) -> usize {
+        self.body.trim().chars().count() / 5
+    }
+
+    /// Entity kind derived from the definition filename (e.g. `SKILL.md` -> `skill`).
+    pub fn kind(&self) -> String {
+        self.path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+}
+
 // New types for parsing stages
 pub struct ValidatedPath(PathBuf);
 pub struct FileContent {
@@ -135,10 +236,15 @@ impl TryFrom<SplitContent> for ParsedEntity {
             return Err(PersonaError::InvalidNameFormat(frontmatter.name));
         }
 
-        if frontmatter.description.trim().is_empty() {
+        // Extending and removing entities only carry what changes.
+        let partial = matches!(frontmatter.layer, Some(Layer::Extend | Layer::Remove));
+
+        if frontmatter.description.trim().is_empty() && !partial {
             return Err(PersonaError::EmptyDescription);
         }
 
+        validate_frontmatter_value("", &frontmatter.other)?;
+
         let parent_dir_name = split
             .path
             .0
@@ -156,7 +262,7 @@ impl TryFrom<SplitContent> for ParsedEntity {
             });
         }
 
-        if split.body.trim().is_empty() {
+        if split.body.trim().is_empty() && !partial {
             return Err(PersonaError::EmptyBody);
         }
 
@@ -169,7 +275,8 @@ impl TryFrom<SplitContent> for ParsedEntity {
     }
 }
 
-fn extract_frontmatter_and_body(content: &str) -> Result<(&str, &str), PersonaError> {
+/// Splits a document into its YAML frontmatter and body.
+pub fn extract_frontmatter_and_body(content: &str) -> Result<(&str, &str), PersonaError> {
     let trimmed_content = content.trim_start();
     if !trimmed_content.starts_with("---") {
         return Err(PersonaError::MissingFrontmatter);
@@ -208,7 +315,44 @@ fn extract_frontmatter_and_body(content: &str) -> Result<(&str, &str), PersonaEr
     ))
 }
 
-fn is_valid_name(name: &str) -> bool {
+/// Ensures every key can be written to XML, either as an element name or as the
+/// `name` attribute of a fallback element.
+fn validate_frontmatter_value(key: &str, value: &serde_yaml::Value) -> Result<(), PersonaError> {
+    match value {
+        serde_yaml::Value::Mapping(map) => {
+            for (k, v) in map {
+                let key_str = match k {
+                    serde_yaml::Value::String(s) => s.clone(),
+                    serde_yaml::Value::Number(n) => n.to_string(),
+                    serde_yaml::Value::Bool(b) => b.to_string(),
+                    other => {
+                        return Err(PersonaError::InvalidFrontmatterKey(format!("{:?}", other)));
+                    }
+                };
+                if key_str.chars().any(|c| c.is_control()) {
+                    return Err(PersonaError::InvalidFrontmatterKey(format!(
+                        "{:?}",
+                        key_str
+                    )));
+                }
+                validate_frontmatter_value(&key_str, v)?;
+            }
+        }
+        serde_yaml::Value::Sequence(seq) => {
+            for item in seq {
+                validate_frontmatter_value(key, item)?;
+            }
+        }
+        serde_yaml::Value::Tagged(_) => {
+            return Err(PersonaError::UnsupportedFrontmatterValue(key.to_string()));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Entity names are 1-64 characters of lowercase ASCII letters, digits and hyphens.
+pub fn is_valid_name(name: &str) -> bool {
     if name.is_empty() || name.len() > 64 {
         return false;
     }
@@ -232,6 +376,37 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_one_or_many_fields() {
+        let parse = |yaml: &str| serde_yaml::from_str::<Frontmatter>(yaml);
+
+        let single = parse("name: qa\nrequires: b\nskills: git\ntags: backend").unwrap();
+        assert_eq!(single.requires, vec!["b"]);
+        assert_eq!(single.skills, vec!["git"]);
+        assert_eq!(single.tags, vec!["backend"]);
+        assert!(single.other.get("tags").is_none());
+
+        let many = parse("name: qa\nskills: [git, lang/rust]\ntags:\n").unwrap();
+        assert_eq!(many.skills, vec!["git", "lang/rust"]);
+        assert!(many.tags.is_empty());
+
+        // Scalars in a list are read as strings.
+        assert_eq!(
+            parse("name: qa\ntags: [x, 1]").unwrap().tags,
+            vec!["x", "1"]
+        );
+        assert!(parse("name: qa\nrequires:\n").unwrap().requires.is_empty());
+        for invalid in [
+            "tags: [[x]]",
+            "tags: {x: y}",
+            "skills: 3",
+            "requires: {x: y}",
+        ] {
+            let error = parse(&format!("name: qa\n{invalid}")).unwrap_err();
+            assert!(error.to_string().contains("string"), "{invalid}: {error}");
+        }
+    }
+
     #[test]
     fn test_frontmatter_extraction() {
         let content = "---\nkey: value\n---\nbody";
@@ -256,6 +431,60 @@ mod tests {
         assert!(matches!(result, Err(PersonaError::MissingFrontmatter)));
     }
 
+    #[test]
+    fn test_frontmatter_key_validation() {
+        let valid: serde_yaml::Value =
+            serde_yaml::from_str("my key: a\n1: b\nnested:\n  true: c\n  list: [1, 2]").unwrap();
+        assert!(validate_frontmatter_value("", &valid).is_ok());
+
+        let non_scalar: serde_yaml::Value = serde_yaml::from_str("nested:\n  [a, b]: c").unwrap();
+        assert!(matches!(
+            validate_frontmatter_value("", &non_scalar),
+            Err(PersonaError::InvalidFrontmatterKey(_))
+        ));
+
+        let control: serde_yaml::Value = serde_yaml::from_str("\"bad\\tkey\": c").unwrap();
+        assert!(matches!(
+            validate_frontmatter_value("", &control),
+            Err(PersonaError::InvalidFrontmatterKey(_))
+        ));
+
+        let tagged: serde_yaml::Value = serde_yaml::from_str("value: !custom x").unwrap();
+        assert!(matches!(
+            validate_frontmatter_value("", &tagged),
+            Err(PersonaError::UnsupportedFrontmatterValue(k)) if k == "value"
+        ));
+    }
+
+    #[test]
+    fn test_layer_directive() {
+        let split = |frontmatter: &str, body: &str| SplitContent {
+            path: ValidatedPath(PathBuf::from("skills/git/SKILL.md")),
+            frontmatter_str: frontmatter.to_string(),
+            body: body.to_string(),
+            char_count: 0,
+        };
+
+        let extend =
+            ParsedEntity::try_from(split("name: git\nlayer: extend\ntags: [vcs]", "")).unwrap();
+        assert_eq!(extend.frontmatter.layer, Some(Layer::Extend));
+        assert!(ParsedEntity::try_from(split("name: git\nlayer: remove", "")).is_ok());
+
+        // Overriding entities replace the earlier one and must be complete.
+        assert!(matches!(
+            ParsedEntity::try_from(split("name: git\nlayer: override", "body")),
+            Err(PersonaError::EmptyDescription)
+        ));
+        assert!(matches!(
+            ParsedEntity::try_from(split("name: git\ndescription: d\nlayer: override", "")),
+            Err(PersonaError::EmptyBody)
+        ));
+        assert!(matches!(
+            ParsedEntity::try_from(split("name: git\ndescription: d\nlayer: merge", "body")),
+            Err(PersonaError::Yaml(_))
+        ));
+    }
+
     #[test]
     fn test_name_validation() {
         assert!(is_valid_name("valid-name-123"));
@@ -81,7 +81,11 @@ proptest! {
 
         if let Ok(entity) = result {
             prop_assert_eq!(entity.frontmatter.name, name);
+            // Known fields are typed and not kept in `other`
             for (k, _v) in extra_fields {
+                if ["inline", "order", "priority", "requires", "skills", "tags", "deprecated", "layer", "when"].contains(&k.as_str()) {
+                    continue;
+                }
                 prop_assert!(entity.frontmatter.other.get(&k).is_some(), "Field {} missing from other", k);
             }
         }